@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use reqwest::header::HeaderMap;
+use reqwest::redirect::Policy;
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+use serde::Deserialize;
+
+const MAX_REDIRECTS: usize = 10;
+
+// Credentials for private repositories, in composer's auth.json format. Read from the global
+// COMPOSER_HOME/auth.json, then ./auth.json, then the COMPOSER_AUTH env var; later ones win per host.
+// Deliberately not Debug/Serialize, so secrets can't end up in output or in composer.lock.
+#[derive(Default, Deserialize)]
+pub struct Auth {
+    #[serde(default, rename = "http-basic")]
+    http_basic: BTreeMap<String, Basic>,
+    #[serde(default)]
+    bearer: BTreeMap<String, String>,
+    #[serde(default, rename = "gitlab-token")]
+    gitlab_token: BTreeMap<String, GitlabToken>,
+    #[serde(default, rename = "github-oauth")]
+    github_oauth: BTreeMap<String, String>,
+    // "host": ["Header-Name: value", ...]
+    #[serde(default, rename = "custom-headers")]
+    custom_headers: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Basic {
+    username: String,
+    #[serde(default)]
+    password: String,
+}
+
+// a personal access token, or a deploy token with its username
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GitlabToken {
+    Private(String),
+    Deploy { username: String, token: String },
+}
+
+impl Auth {
+    pub fn load() -> Result<Self> {
+        let mut auth = Self::default();
+        if let Some(home) = composer_home() {
+            auth.merge(read(&home.join("auth.json"))?);
+        }
+        auth.merge(read(Path::new("auth.json"))?);
+        if let Ok(env) = std::env::var("COMPOSER_AUTH")
+            && !env.trim().is_empty()
+        {
+            auth.merge(serde_json::from_str(&env).context("COMPOSER_AUTH is not valid JSON")?);
+        }
+        Ok(auth)
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.http_basic.extend(other.http_basic);
+        self.bearer.extend(other.bearer);
+        self.gitlab_token.extend(other.gitlab_token);
+        self.github_oauth.extend(other.github_oauth);
+        self.custom_headers.extend(other.custom_headers);
+    }
+
+    // GET a url with the credentials configured for its host. reqwest only strips Authorization when a
+    // redirect leaves the host, not PRIVATE-TOKEN or custom headers, so clients built with
+    // `redirect_policy` stop there and the redirect is followed here, with the new host's credentials.
+    pub async fn get(&self, client: &Client, url: &str, headers: HeaderMap) -> reqwest::Result<Response> {
+        let mut url = url.to_string();
+        let mut hops = 0;
+        loop {
+            let response = self.apply(client.get(&url).headers(headers.clone()), &url).send().await?;
+            let redirect = matches!(
+                response.status(),
+                StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER
+                    | StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT
+            );
+            let location = response.headers().get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| response.url().join(location).ok());
+            match location {
+                Some(next) if redirect && hops < MAX_REDIRECTS => {
+                    url = next.to_string();
+                    hops += 1;
+                }
+                _ => return Ok(response),
+            }
+        }
+    }
+
+    // Add the credentials configured for the url's host (or host:port) to a request.
+    pub fn apply(&self, mut request: RequestBuilder, url: &str) -> RequestBuilder {
+        let Ok(parsed) = Url::parse(url) else {
+            return request;
+        };
+        let Some(host) = parsed.host_str() else {
+            return request;
+        };
+        let origin = match parsed.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+
+        if let Some(basic) = lookup(&self.http_basic, &origin, host) {
+            request = request.basic_auth(&basic.username, Some(&basic.password));
+        } else if let Some(token) = lookup(&self.bearer, &origin, host) {
+            request = request.bearer_auth(token);
+        } else if let Some(token) = lookup(&self.gitlab_token, &origin, host) {
+            request = match token {
+                GitlabToken::Private(token) => request.header("PRIVATE-TOKEN", token),
+                GitlabToken::Deploy { username, token } => request.basic_auth(username, Some(token)),
+            };
+        } else if let Some(token) = github_token(&self.github_oauth, host) {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("token {}", token));
+        }
+
+        for header in lookup(&self.custom_headers, &origin, host).into_iter().flatten() {
+            if let Some((name, value)) = header.split_once(':') {
+                request = request.header(name.trim(), value.trim());
+            }
+        }
+        request
+    }
+}
+
+// follow redirects within an origin only; see `Auth::get`
+pub fn redirect_policy() -> Policy {
+    Policy::custom(|attempt| {
+        let left = attempt.previous().last().is_some_and(|previous| previous.origin() != attempt.url().origin());
+        if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if left {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+fn lookup<'a, T>(map: &'a BTreeMap<String, T>, origin: &str, host: &str) -> Option<&'a T> {
+    map.get(origin).or_else(|| map.get(host))
+}
+
+// tokens are configured for "github.com" but the api lives on api.github.com
+fn github_token<'a>(tokens: &'a BTreeMap<String, String>, host: &str) -> Option<&'a String> {
+    tokens.get(host).or_else(|| tokens.get(host.strip_prefix("api.")?))
+}
+
+// a missing file is fine, a broken one is not
+fn read(path: &Path) -> Result<Auth> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).with_context(|| format!("Invalid {}", path.display())),
+        Err(_) => Ok(Auth::default()),
+    }
+}
+
+// $COMPOSER_HOME, else ~/.config/composer, else ~/.composer (same lookup as composer)
+fn composer_home() -> Option<PathBuf> {
+    if let Ok(home) = std::env::var("COMPOSER_HOME")
+        && !home.is_empty()
+    {
+        return Some(PathBuf::from(home));
+    }
+    let config = dirs::config_dir().map(|dir| dir.join("composer"));
+    match config {
+        Some(dir) if dir.is_dir() => Some(dir),
+        _ => dirs::home_dir().map(|dir| dir.join(".composer")),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn headers(auth: &Auth, url: &str) -> reqwest::header::HeaderMap {
+        let request = auth.apply(reqwest::Client::new().get(url), url);
+        request.build().unwrap().headers().clone()
+    }
+
+    #[test]
+    fn applies_credentials_per_host() {
+        let auth: Auth = serde_json::from_str(r#"{
+            "http-basic": {"repo.example.com": {"username": "u", "password": "p"}},
+            "bearer": {"localhost:8080": "secret"},
+            "gitlab-token": {"gitlab.com": "glpat", "gitlab.example.com": {"username": "deploy", "token": "t"}},
+            "github-oauth": {"github.com": "ghp"},
+            "custom-headers": {"repo.example.com": ["X-Api-Key: k"]}
+        }"#).unwrap();
+
+        let basic = headers(&auth, "https://repo.example.com/packages.json");
+        assert_eq!(basic["authorization"], "Basic dTpw");
+        assert_eq!(basic["x-api-key"], "k");
+        assert_eq!(headers(&auth, "http://localhost:8080/p2/a/b.json")["authorization"], "Bearer secret");
+        assert!(headers(&auth, "http://localhost/p2/a/b.json").get("authorization").is_none());
+        assert_eq!(headers(&auth, "https://gitlab.com/api/v4/x")["private-token"], "glpat");
+        assert_eq!(headers(&auth, "https://gitlab.example.com/x")["authorization"], "Basic ZGVwbG95OnQ=");
+        assert_eq!(headers(&auth, "https://api.github.com/repos/a/b/zipball/1")["authorization"], "token ghp");
+        assert!(headers(&auth, "https://example.com/x").is_empty());
+    }
+
+    // answer each connection in turn with the given responses, handing back the requests received
+    pub(crate) fn serve(responses: Vec<String>) -> (u16, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            responses.into_iter().map(|response| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut request).unwrap() > 2 {}
+                stream.write_all(response.as_bytes()).unwrap();
+                request.to_lowercase()
+            }).collect()
+        });
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn sends_each_host_its_own_credentials_across_redirects() {
+        let (mirror, mirror_requests) = serve(vec!["HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok".into()]);
+        let redirect = |path: String| format!("HTTP/1.1 302 Found\r\nlocation: {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", path);
+        let (repo, repo_requests) = serve(vec![
+            redirect("/moved".into()),
+            redirect(format!("http://127.0.0.1:{}/dist.zip", mirror)),
+        ]);
+
+        let auth: Auth = serde_json::from_str(&format!(r#"{{
+            "gitlab-token": {{"127.0.0.1:{repo}": "glpat"}},
+            "custom-headers": {{"127.0.0.1:{repo}": ["X-Api-Key: k"]}},
+            "bearer": {{"127.0.0.1:{mirror}": "mirror-token"}}
+        }}"#)).unwrap();
+        let client = Client::builder().redirect(redirect_policy()).build().unwrap();
+        let response = auth.get(&client, &format!("http://127.0.0.1:{}/dist.zip", repo), HeaderMap::new()).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        // redirects within the repository keep its credentials
+        let repo_requests = repo_requests.join().unwrap();
+        assert!(repo_requests[1].starts_with("get /moved "));
+        assert!(repo_requests.iter().all(|r| r.contains("private-token: glpat") && r.contains("x-api-key: k")));
+
+        let mirror_requests = mirror_requests.join().unwrap();
+        assert!(!mirror_requests[0].contains("private-token") && !mirror_requests[0].contains("x-api-key"));
+        assert!(mirror_requests[0].contains("authorization: bearer mirror-token"));
+    }
+}
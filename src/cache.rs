@@ -1,10 +1,21 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use anyhow::Result;
 use dirs::cache_dir;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+// HTTP validators of a cached metadata file, so an update can ask whether it changed
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, rename = "last-modified", skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
 
 pub struct Cache {
     root: PathBuf,
+    metadata: PathBuf,
 }
 
 impl Cache {
@@ -17,18 +28,85 @@ impl Cache {
         fs::create_dir_all(path.join("metadata")).ok();
         fs::create_dir_all(path.join("dist")).ok();
 
-        Self { root: path }
+        Self { metadata: path.join("metadata"), root: path }
+    }
+
+    // metadata of repositories other than packagist is kept apart, since names may overlap:
+    // ~/.cache/maestro/metadata/packages.example.com/vendor-package.json
+    pub fn for_repository(url: &str) -> Self {
+        let mut cache = Self::new();
+        cache.metadata = cache.metadata.join(sanitize(url));
+        fs::create_dir_all(&cache.metadata).ok();
+        cache
+    }
+
+    // git mirrors of vcs repositories: ~/.cache/maestro/vcs/github.com-vendor-package.git
+    pub fn get_vcs_path(&self, url: &str) -> PathBuf {
+        self.root.join("vcs").join(sanitize(url))
     }
 
     // get path for metadata cache: ~/.cache/maestro/metadata/vendor-package.json
     pub fn get_metadata_path(&self, pkg_name: &str) -> PathBuf {
         let safe_name = pkg_name.replace('/', "-");
-        self.root.join("metadata").join(format!("{}.json", safe_name))
+        self.metadata.join(format!("{}.json", safe_name))
     }
 
-    // Get path for dist cache: ~/.cache/metadata/dist/vendor-package-version.zip
-    pub fn get_dist_path(&self, pkg_name: &str, version: &str) -> PathBuf {
+    // dev branches are cached separately: ~/.cache/maestro/metadata/vendor-package~dev.json
+    pub fn get_dev_metadata_path(&self, pkg_name: &str) -> PathBuf {
         let safe_name = pkg_name.replace('/', "-");
-        self.root.join("dist").join(format!("{}-{}.zip", safe_name, version))
+        self.metadata.join(format!("{}~dev.json", safe_name))
+    }
+
+    // validators live next to the file they describe: vendor-package.json.headers
+    pub fn read_validators(&self, path: &Path) -> Validators {
+        fs::read_to_string(validators_path(path)).ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn write_validators(&self, path: &Path, validators: &Validators) {
+        let path = validators_path(path);
+        if validators.etag.is_none() && validators.last_modified.is_none() {
+            fs::remove_file(path).ok();
+        } else if let Ok(content) = serde_json::to_string(validators) {
+            fs::write(path, content).ok();
+        }
     }
-}
\ No newline at end of file
+
+    // Get path for dist cache, keyed by the dist reference like composer, so a tag moved to another
+    // commit is downloaded again: ~/.cache/maestro/dist/vendor/package/<reference>.zip (or .tar.gz, ...).
+    // Dists without a reference are keyed by their url.
+    pub fn get_dist_path(&self, pkg_name: &str, reference: Option<&str>, url: &str, dist_type: &str) -> PathBuf {
+        let key = match reference {
+            Some(reference) if !reference.is_empty() => reference.replace(['/', '\\'], "-"),
+            _ => hex::encode(Sha1::digest(url.as_bytes())),
+        };
+        self.root.join("dist").join(pkg_name).join(format!("{}.{}", key, archive_extension(dist_type)))
+    }
+}
+
+// dist type -> file extension; composer's "xz" and "tar" types are tarballs
+fn archive_extension(dist_type: &str) -> &str {
+    match dist_type {
+        "tgz" | "tar.gz" => "tar.gz",
+        "tbz2" | "tar.bz2" => "tar.bz2",
+        "xz" | "txz" | "tar.xz" => "tar.xz",
+        "" => "zip",
+        other => other,
+    }
+}
+
+fn validators_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".headers");
+    PathBuf::from(name)
+}
+
+// "https://packages.example.com/" -> "packages.example.com"
+fn sanitize(url: &str) -> String {
+    let url = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    url.trim_end_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '-' })
+        .collect()
+}
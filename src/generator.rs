@@ -1,49 +1,40 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use anyhow::{Context, Result};
+use crate::installed::{self, Installed};
 use crate::manifest::ComposerManifest;
+use crate::repository::{guess_version, head_reference};
+use crate::version::normalize;
 use colored::Colorize;
 
-pub fn generate_autoload(vendor_dir: &str) -> Result<()> {
+pub fn generate_autoload(vendor_dir: &str, packages: &[String]) -> Result<()> {
     println!("{}", "Generating autoload files...".cyan());
 
     let mut psr4_map: HashMap<String, Vec<String>> = HashMap::new();
     let vendor_path = Path::new(vendor_dir);
 
-    // scan vendor dir for installed packages
+    // only the packages being installed are autoloaded (e.g. not require-dev under --no-dev)
     // structure: vendor/VENDOR/PACKAGE/composer.json
-    for entry in fs::read_dir(vendor_path)? {
-        let entry = entry?;
-        if !entry.path().is_dir() { continue; } 
-
-        // "vendor_name" dir (i.e. "monolog")
-        for pkg_entry in fs::read_dir(entry.path())? {
-            let pkg_entry = pkg_entry?;
-            if !pkg_entry.path().is_dir() { continue; }
-
-            let composer_path = pkg_entry.path().join("composer.json");
-            if !composer_path.exists() { continue; }
-
-            // parse packages's composer.json
-            let content = fs::read_to_string(&composer_path)?;
-            let manifest: ComposerManifest = match serde_json::from_str(&content) {
-                Ok(m) => m,
-                Err(_) => continue, 
-            };
-
-            // extract psr-4 rules
-            for (namespace, path) in manifest.autoload.psr4 {
-                // use paths relative to the 'vendor' root
-                // path format: $vendorDir . '/vendor_name/package_name/src'
-
-                let pkg_name_path = pkg_entry.path().strip_prefix(vendor_path)?.to_str().unwrap().to_string();
-
-                let full_path = format!("$vendorDir .'/{}/{}'", pkg_name_path, path);
-
-                // add to map
-                psr4_map.entry(namespace).or_default().push(full_path);
-            }
+    for name in packages {
+        let composer_path = vendor_path.join(name).join("composer.json");
+        if !composer_path.exists() { continue; }
+
+        // parse packages's composer.json
+        let content = fs::read_to_string(&composer_path)?;
+        let manifest: ComposerManifest = match serde_json::from_str(&content) {
+            Ok(m) => m,
+            Err(_) => continue, 
+        };
+
+        // extract psr-4 rules
+        for (namespace, path) in manifest.autoload.psr4 {
+            // use paths relative to the 'vendor' root
+            // path format: $vendorDir . '/vendor_name/package_name/src'
+            let full_path = format!("$vendorDir .'/{}/{}'", name, path);
+
+            // add to map
+            psr4_map.entry(namespace).or_default().push(full_path);
         }
     }
     let composer_dir = vendor_path.join("composer");
@@ -96,6 +87,12 @@ class ComposerAutoloaderInitMaestro {
         self::$loader = $loader = new \Composer\Autoload\ClassLoader();
         spl_autoload_unregister(array('ComposerAutoloaderInitMaestro', 'loadClassLoader'));
 
+        spl_autoload_register(function ($class) {
+            if ('Composer\InstalledVersions' === $class && is_file(__DIR__ . '/InstalledVersions.php')) {
+                require __DIR__ . '/InstalledVersions.php';
+            }
+        });
+
         $map = require __DIR__ . '/autoload_psr4.php';
         foreach ($map as $namespace => $path) {
             $loader->addPsr4($namespace, $path);
@@ -214,4 +211,474 @@ function includeFile($file)
 
     Ok(())
 
-}
\ No newline at end of file
+}
+// What installed.php says about one name: a package in vendor/ (or the root), or just a name
+// that installed packages provide or replace.
+#[derive(Default)]
+struct VersionEntry {
+    pretty_version: Option<String>,
+    version: Option<String>,
+    reference: Option<String>,
+    package_type: Option<String>,
+    // php expression, relative to vendor/composer; None for metapackages
+    install_path: Option<String>,
+    dev_requirement: bool,
+    provided: Vec<String>,
+    replaced: Vec<String>,
+}
+
+// Write vendor/composer/installed.php and the Composer\InstalledVersions class that reads it, which
+// libraries use to ask at runtime what is installed (Composer\InstalledVersions::getVersion(...)).
+pub fn generate_installed_versions(vendor_dir: &str, root: &ComposerManifest, installed: &Installed) -> Result<()> {
+    let composer_dir = Path::new(vendor_dir).join("composer");
+    fs::create_dir_all(&composer_dir)?;
+
+    fs::write(composer_dir.join("installed.php"), installed_php(root, Path::new("."), installed))
+        .context("Failed to write installed.php")?;
+    fs::write(composer_dir.join("InstalledVersions.php"), INSTALLED_VERSIONS_SOURCE)
+        .context("Failed to write InstalledVersions.php")?;
+    Ok(())
+}
+
+// the contents of installed.php, for a root package checked out in `root_dir`
+fn installed_php(root: &ComposerManifest, root_dir: &Path, installed: &Installed) -> String {
+    let dev_names: HashSet<&str> = installed.dev_package_names.iter().map(String::as_str).collect();
+    let root_name = root.name.clone().unwrap_or_else(|| "__root__".to_string());
+
+    // the root's version is what composer.json says, else what git has checked out
+    let root_pretty = root.extra.get("version").and_then(|v| v.as_str()).map(String::from)
+        .or_else(|| guess_version(root_dir))
+        .unwrap_or_else(|| "1.0.0+no-version-set".to_string());
+    let root_version = normalize(&root_pretty).unwrap_or_else(|_| "1.0.0.0".to_string());
+    let root_entry = VersionEntry {
+        pretty_version: Some(root_pretty),
+        version: Some(root_version),
+        reference: head_reference(root_dir),
+        package_type: Some(root.extra.get("type").and_then(|v| v.as_str()).unwrap_or("library").to_string()),
+        install_path: Some("__DIR__ . '/../../'".to_string()),
+        ..Default::default()
+    };
+
+    let mut versions: BTreeMap<String, VersionEntry> = BTreeMap::new();
+    for entry in &installed.packages {
+        let package = &entry.package;
+        let Some(name) = package.name.clone() else { continue };
+        let package_type = package.package_type.clone().unwrap_or_else(|| "library".to_string());
+        let install_path = (package_type != "metapackage")
+            .then(|| format!("__DIR__ . {}", php_string(&format!("/{}", entry.install_path))));
+        versions.insert(name.clone(), VersionEntry {
+            pretty_version: Some(package.version.clone()),
+            version: Some(package.version_normalized.clone()),
+            reference: installed::reference(package).map(String::from),
+            package_type: Some(package_type),
+            install_path,
+            dev_requirement: dev_names.contains(name.as_str()),
+            ..Default::default()
+        });
+    }
+
+    // provided and replaced names count as dev requirements only if every package behind them is one
+    let mut virtual_names = |links: &mut dyn Iterator<Item = (&String, &String)>, pretty_version: &str, dev: bool, replaced: bool| {
+        for (name, constraint) in links {
+            let constraint = if constraint == "self.version" { pretty_version.to_string() } else { constraint.clone() };
+            let entry = versions.entry(name.to_lowercase()).or_insert_with(|| VersionEntry { dev_requirement: true, ..Default::default() });
+            if entry.pretty_version.is_none() {
+                entry.dev_requirement &= dev;
+            }
+            if replaced { entry.replaced.push(constraint) } else { entry.provided.push(constraint) }
+        }
+    };
+    virtual_names(&mut root.provide.iter(), root_entry.pretty_version.as_deref().unwrap_or_default(), false, false);
+    virtual_names(&mut root.replace.iter(), root_entry.pretty_version.as_deref().unwrap_or_default(), false, true);
+    for entry in &installed.packages {
+        let package = &entry.package;
+        let dev = package.name.as_deref().is_some_and(|name| dev_names.contains(name));
+        virtual_names(&mut package.provide.iter(), &package.version, dev, false);
+        virtual_names(&mut package.replace.iter(), &package.version, dev, true);
+    }
+
+    let mut php = String::from("<?php return array(\n    'root' => array(\n");
+    php.push_str(&format!("        'name' => {},\n", php_string(&root_name)));
+    push_version_entry(&mut php, &root_entry, "        ");
+    php.push_str(&format!("        'dev' => {},\n    ),\n    'versions' => array(\n", installed.dev));
+    // the root is listed among the versions too
+    versions.entry(root_name).or_insert(root_entry);
+    for (name, entry) in &versions {
+        php.push_str(&format!("        {} => array(\n", php_string(name)));
+        push_version_entry(&mut php, entry, "            ");
+        php.push_str(&format!("            'dev_requirement' => {},\n", entry.dev_requirement));
+        for (key, values) in [("provided", &entry.provided), ("replaced", &entry.replaced)] {
+            if values.is_empty() {
+                continue;
+            }
+            php.push_str(&format!("            '{}' => array(\n", key));
+            for (i, value) in values.iter().enumerate() {
+                php.push_str(&format!("                {} => {},\n", i, php_string(value)));
+            }
+            php.push_str("            ),\n");
+        }
+        php.push_str("        ),\n");
+    }
+    php.push_str("    ),\n);\n");
+    php
+}
+
+// the keys shared by the root and versions entries; names that are only provided or replaced have none
+fn push_version_entry(php: &mut String, entry: &VersionEntry, indent: &str) {
+    let Some(pretty_version) = &entry.pretty_version else { return };
+    let or_null = |value: &Option<String>| value.as_deref().map(php_string).unwrap_or_else(|| "NULL".to_string());
+    php.push_str(&format!("{}'pretty_version' => {},\n", indent, php_string(pretty_version)));
+    php.push_str(&format!("{}'version' => {},\n", indent, or_null(&entry.version)));
+    php.push_str(&format!("{}'reference' => {},\n", indent, or_null(&entry.reference)));
+    php.push_str(&format!("{}'type' => {},\n", indent, or_null(&entry.package_type)));
+    php.push_str(&format!("{}'install_path' => {},\n", indent, entry.install_path.as_deref().unwrap_or("NULL")));
+    php.push_str(&format!("{}'aliases' => array(),\n", indent));
+}
+
+// a single-quoted php string literal
+fn php_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+// API-compatible with composer's class of the same name, reading the single vendor/ Maestro manages
+const INSTALLED_VERSIONS_SOURCE: &str = r#"<?php
+
+namespace Composer;
+
+use Composer\Semver\VersionParser;
+
+/**
+ * Runtime information about the installed packages, generated by Maestro.
+ * Compatible with the class of the same name that Composer generates.
+ */
+class InstalledVersions
+{
+    /**
+     * @var array|null
+     */
+    private static $installed;
+
+    /**
+     * Names of all installed packages, including names that are only provided or replaced
+     *
+     * @return string[]
+     */
+    public static function getInstalledPackages()
+    {
+        $packages = array();
+        foreach (self::getInstalled() as $installed) {
+            $packages[] = array_keys($installed['versions']);
+        }
+
+        if (1 === \count($packages)) {
+            return $packages[0];
+        }
+
+        return array_keys(array_flip(\call_user_func_array('array_merge', $packages)));
+    }
+
+    /**
+     * @param  string   $type
+     * @return string[]
+     */
+    public static function getInstalledPackagesByType($type)
+    {
+        $packagesByType = array();
+
+        foreach (self::getInstalled() as $installed) {
+            foreach ($installed['versions'] as $name => $package) {
+                if (isset($package['type']) && $package['type'] === $type) {
+                    $packagesByType[] = $name;
+                }
+            }
+        }
+
+        return $packagesByType;
+    }
+
+    /**
+     * @param  string $packageName
+     * @param  bool   $includeDevRequirements
+     * @return bool
+     */
+    public static function isInstalled($packageName, $includeDevRequirements = true)
+    {
+        foreach (self::getInstalled() as $installed) {
+            if (isset($installed['versions'][$packageName])) {
+                return $includeDevRequirements || !isset($installed['versions'][$packageName]['dev_requirement']) || $installed['versions'][$packageName]['dev_requirement'] === false;
+            }
+        }
+
+        return false;
+    }
+
+    /**
+     * Whether the installed version of a package satisfies a constraint (needs composer/semver)
+     *
+     * @param  string      $packageName
+     * @param  string|null $constraint
+     * @return bool
+     */
+    public static function satisfies(VersionParser $parser, $packageName, $constraint)
+    {
+        $constraint = $parser->parseConstraints((string) $constraint);
+        $provided = $parser->parseConstraints(self::getVersionRanges($packageName));
+
+        return $provided->matches($constraint);
+    }
+
+    /**
+     * @param  string $packageName
+     * @return string Version constraint usable with composer/semver
+     */
+    public static function getVersionRanges($packageName)
+    {
+        foreach (self::getInstalled() as $installed) {
+            if (!isset($installed['versions'][$packageName])) {
+                continue;
+            }
+
+            $ranges = array();
+            if (isset($installed['versions'][$packageName]['pretty_version'])) {
+                $ranges[] = $installed['versions'][$packageName]['pretty_version'];
+            }
+            foreach (array('aliases', 'replaced', 'provided') as $key) {
+                if (array_key_exists($key, $installed['versions'][$packageName])) {
+                    $ranges = array_merge($ranges, $installed['versions'][$packageName][$key]);
+                }
+            }
+
+            return implode(' || ', $ranges);
+        }
+
+        throw new \OutOfBoundsException('Package "' . $packageName . '" is not installed');
+    }
+
+    /**
+     * @param  string      $packageName
+     * @return string|null
+     */
+    public static function getVersion($packageName)
+    {
+        return self::getField($packageName, 'version');
+    }
+
+    /**
+     * @param  string      $packageName
+     * @return string|null
+     */
+    public static function getPrettyVersion($packageName)
+    {
+        return self::getField($packageName, 'pretty_version');
+    }
+
+    /**
+     * @param  string      $packageName
+     * @return string|null
+     */
+    public static function getReference($packageName)
+    {
+        return self::getField($packageName, 'reference');
+    }
+
+    /**
+     * @param  string      $packageName
+     * @return string|null
+     */
+    public static function getInstallPath($packageName)
+    {
+        return self::getField($packageName, 'install_path');
+    }
+
+    /**
+     * @return array{name: string, pretty_version: string, version: string, reference: string|null, type: string, install_path: string, aliases: string[], dev: bool}
+     */
+    public static function getRootPackage()
+    {
+        $installed = self::getInstalled();
+
+        return $installed[0]['root'];
+    }
+
+    /**
+     * @return array
+     */
+    public static function getRawData()
+    {
+        $installed = self::getInstalled();
+
+        return $installed[0];
+    }
+
+    /**
+     * @return array[]
+     */
+    public static function getAllRawData()
+    {
+        return self::getInstalled();
+    }
+
+    /**
+     * Replace the data, e.g. in tests
+     *
+     * @param  array $data
+     * @return void
+     */
+    public static function reload($data)
+    {
+        self::$installed = $data;
+    }
+
+    /**
+     * @param  string $packageName
+     * @param  string $field
+     * @return string|null
+     */
+    private static function getField($packageName, $field)
+    {
+        foreach (self::getInstalled() as $installed) {
+            if (!isset($installed['versions'][$packageName])) {
+                continue;
+            }
+
+            if (!isset($installed['versions'][$packageName][$field])) {
+                return null;
+            }
+
+            return $installed['versions'][$packageName][$field];
+        }
+
+        throw new \OutOfBoundsException('Package "' . $packageName . '" is not installed');
+    }
+
+    /**
+     * @return array[]
+     */
+    private static function getInstalled()
+    {
+        if (null === self::$installed) {
+            self::$installed = is_file(__DIR__ . '/installed.php') ? require __DIR__ . '/installed.php' : array('root' => array(), 'versions' => array());
+        }
+
+        return array(self::$installed);
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{DistInfo, PackageVersion};
+
+    fn package(name: &str, version: &str, package_type: &str) -> PackageVersion {
+        PackageVersion {
+            name: Some(name.into()),
+            version: version.into(),
+            version_normalized: normalize(version).unwrap(),
+            package_type: Some(package_type.into()),
+            dist: Some(DistInfo {
+                r#type: "zip".into(),
+                url: format!("https://example.com/{}.zip", name),
+                reference: Some(format!("{}-ref", name.replace('/', "-"))),
+                shasum: None,
+                sha256: None,
+                mirrors: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn writes_installed_php() {
+        let root: ComposerManifest = serde_json::from_str(r#"{
+            "name": "acme/app",
+            "version": "1.2.0",
+            "type": "project",
+            "replace": {"acme/legacy": "self.version"},
+            "provide": {"psr/log-implementation": "3.0.0"}
+        }"#).unwrap();
+        let mut monolog = package("monolog/monolog", "3.5.0", "library");
+        monolog.provide = BTreeMap::from([("psr/log-implementation".to_string(), "3.0.0".to_string())]);
+        let mut tools = package("acme/tools", "2.0.0", "library");
+        tools.replace = BTreeMap::from([("acme/tools-cli".to_string(), "self.version".to_string())]);
+        let packages = [monolog, tools, package("acme/bundle", "1.0.0", "metapackage")];
+        let installed = Installed::new(&packages, vec!["acme/tools".into()], true);
+        let root_dir = std::env::temp_dir().join(format!("maestro-installed-{}", std::process::id()));
+        fs::create_dir_all(&root_dir).unwrap();
+
+        let php = installed_php(&root, &root_dir, &installed);
+        fs::remove_dir_all(&root_dir).unwrap();
+        // the root is not a git checkout, so it has no reference
+        assert_eq!(php, r#"
+<?php return array(
+    'root' => array(
+        'name' => 'acme/app',
+        'pretty_version' => '1.2.0',
+        'version' => '1.2.0.0',
+        'reference' => NULL,
+        'type' => 'project',
+        'install_path' => __DIR__ . '/../../',
+        'aliases' => array(),
+        'dev' => true,
+    ),
+    'versions' => array(
+        'acme/app' => array(
+            'pretty_version' => '1.2.0',
+            'version' => '1.2.0.0',
+            'reference' => NULL,
+            'type' => 'project',
+            'install_path' => __DIR__ . '/../../',
+            'aliases' => array(),
+            'dev_requirement' => false,
+        ),
+        'acme/bundle' => array(
+            'pretty_version' => '1.0.0',
+            'version' => '1.0.0.0',
+            'reference' => 'acme-bundle-ref',
+            'type' => 'metapackage',
+            'install_path' => NULL,
+            'aliases' => array(),
+            'dev_requirement' => false,
+        ),
+        'acme/legacy' => array(
+            'dev_requirement' => false,
+            'replaced' => array(
+                0 => '1.2.0',
+            ),
+        ),
+        'acme/tools' => array(
+            'pretty_version' => '2.0.0',
+            'version' => '2.0.0.0',
+            'reference' => 'acme-tools-ref',
+            'type' => 'library',
+            'install_path' => __DIR__ . '/../acme/tools',
+            'aliases' => array(),
+            'dev_requirement' => true,
+        ),
+        'acme/tools-cli' => array(
+            'dev_requirement' => true,
+            'replaced' => array(
+                0 => '2.0.0',
+            ),
+        ),
+        'monolog/monolog' => array(
+            'pretty_version' => '3.5.0',
+            'version' => '3.5.0.0',
+            'reference' => 'monolog-monolog-ref',
+            'type' => 'library',
+            'install_path' => __DIR__ . '/../monolog/monolog',
+            'aliases' => array(),
+            'dev_requirement' => false,
+        ),
+        'psr/log-implementation' => array(
+            'dev_requirement' => false,
+            'provided' => array(
+                0 => '3.0.0',
+                1 => '3.0.0',
+            ),
+        ),
+    ),
+);
+"#.trim_start());
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use crate::lock::to_json;
+use crate::registry::PackageVersion;
+use crate::version::PhpVersion;
+
+// vendor/composer/installed.json in composer 2's format: what Maestro put into vendor/, so the next
+// install knows what to update and what to remove
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Installed {
+    pub packages: Vec<InstalledPackage>,
+    #[serde(default)]
+    pub dev: bool,
+    #[serde(default, rename = "dev-package-names")]
+    pub dev_package_names: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledPackage {
+    #[serde(flatten)]
+    pub package: PackageVersion,
+    // relative to vendor/composer: "../monolog/monolog"
+    #[serde(rename = "install-path")]
+    pub install_path: String,
+}
+
+impl Installed {
+    pub fn new(packages: &[PackageVersion], dev_package_names: Vec<String>, dev: bool) -> Self {
+        let packages = packages.iter()
+            .map(|package| InstalledPackage {
+                install_path: format!("../{}", package.name.as_deref().unwrap_or_default()),
+                package: package.clone(),
+            })
+            .collect();
+        Self { packages, dev, dev_package_names }
+    }
+
+    // nothing installed yet, or by something we can't read (composer 1 wrote a bare list): start over
+    pub fn load(vendor: &Path) -> Self {
+        fs::read_to_string(vendor.join("composer/installed.json")).ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, vendor: &Path) -> Result<()> {
+        let dir = vendor.join("composer");
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("installed.json"), to_json(self)?).context("Failed to write vendor/composer/installed.json")
+    }
+}
+
+// What goes into vendor/: the packages, plus the dev packages unless installing with --no-dev, and
+// the names of the dev packages that went in.
+pub fn select(mut packages: Vec<PackageVersion>, packages_dev: Vec<PackageVersion>, dev: bool) -> (Vec<PackageVersion>, Vec<String>) {
+    let mut dev_package_names = Vec::new();
+    if dev {
+        dev_package_names = packages_dev.iter().filter_map(|p| p.name.clone()).collect();
+        packages.extend(packages_dev);
+    }
+    (packages, dev_package_names)
+}
+
+pub enum Operation<'a> {
+    Install(&'a PackageVersion),
+    Update(&'a PackageVersion, &'a PackageVersion),
+    Uninstall(&'a PackageVersion),
+}
+
+impl Operation<'_> {
+    pub fn name(&self) -> &str {
+        let package = match self {
+            Operation::Install(package) | Operation::Update(_, package) | Operation::Uninstall(package) => package,
+        };
+        package.name.as_deref().unwrap_or_default()
+    }
+}
+
+// What it takes to get from the installed packages to `packages`: removals first, then installs and
+// updates by name. Packages that are installed in the same version and reference need nothing.
+pub fn operations<'a>(installed: &'a Installed, packages: &'a [PackageVersion]) -> Vec<Operation<'a>> {
+    let current: BTreeMap<&str, &PackageVersion> = installed.packages.iter()
+        .filter_map(|entry| Some((entry.package.name.as_deref()?, &entry.package)))
+        .collect();
+    let wanted: HashSet<&str> = packages.iter().filter_map(|p| p.name.as_deref()).collect();
+
+    let mut operations: Vec<Operation> = current.iter()
+        .filter(|(name, _)| !wanted.contains(*name))
+        .map(|(_, package)| Operation::Uninstall(package))
+        .collect();
+
+    let mut changes: Vec<Operation> = packages.iter()
+        .filter_map(|package| {
+            let name = package.name.as_deref()?;
+            match current.get(name) {
+                None => Some(Operation::Install(package)),
+                Some(from) if from.version != package.version || reference(from) != reference(package) => {
+                    Some(Operation::Update(from, package))
+                }
+                Some(_) => None,
+            }
+        })
+        .collect();
+    changes.sort_by(|a, b| a.name().cmp(b.name()));
+    operations.extend(changes);
+    operations
+}
+
+pub fn reference(package: &PackageVersion) -> Option<&str> {
+    package.source.as_ref().and_then(|source| source.reference.as_deref())
+        .or_else(|| package.dist.as_ref().and_then(|dist| dist.reference.as_deref()))
+}
+
+// "1.2.0", or "dev-main 1a2b3c4" where the version alone doesn't say what's installed
+fn pretty(package: &PackageVersion) -> String {
+    let is_dev = package.version.starts_with("dev-") || package.version.ends_with("-dev");
+    match reference(package) {
+        Some(reference) if is_dev => format!("{} {}", package.version, &reference[..reference.len().min(7)]),
+        _ => package.version.clone(),
+    }
+}
+
+// Package operations: 1 install, 1 update, 1 removal
+//   - Removing psr/log (1.1.4)
+//   - Upgrading monolog/monolog (2.9.1 => 3.5.0)
+//   - Installing symfony/console (v6.4.1)
+pub fn print_summary(operations: &[Operation]) {
+    if operations.is_empty() {
+        println!("{}", "Nothing to install, update or remove".green());
+        return;
+    }
+    let count = |f: fn(&Operation) -> bool, word: &str| {
+        let n = operations.iter().filter(|op| f(op)).count();
+        format!("{} {}{}", n, word, if n == 1 { "" } else { "s" })
+    };
+    println!("{}", format!(
+        "Package operations: {}, {}, {}",
+        count(|op| matches!(op, Operation::Install(_)), "install"),
+        count(|op| matches!(op, Operation::Update(..)), "update"),
+        count(|op| matches!(op, Operation::Uninstall(_)), "removal"),
+    ).green());
+
+    for operation in operations {
+        match operation {
+            Operation::Install(package) => {
+                println!("  - Installing {} ({})", operation.name().green(), pretty(package).yellow());
+            }
+            Operation::Update(from, to) => {
+                let downgrade = PhpVersion::from_normalized(&to.version_normalized)
+                    < PhpVersion::from_normalized(&from.version_normalized);
+                let verb = if downgrade { "Downgrading" } else { "Upgrading" };
+                println!("  - {} {} ({} => {})", verb, operation.name().green(), pretty(from).yellow(), pretty(to).yellow());
+            }
+            Operation::Uninstall(package) => {
+                println!("  - Removing {} ({})", operation.name().green(), pretty(package).yellow());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str) -> PackageVersion {
+        PackageVersion { name: Some(name.into()), version: version.into(), ..Default::default() }
+    }
+
+    #[test]
+    fn computes_operations_against_installed_state() {
+        let installed = Installed::new(&[package("a/kept", "1.0.0"), package("b/updated", "1.0.0"), package("c/removed", "1.0.0")], Vec::new(), true);
+        let locked = [package("d/new", "2.0.0"), package("b/updated", "1.1.0"), package("a/kept", "1.0.0")];
+
+        let summary: Vec<String> = operations(&installed, &locked).iter()
+            .map(|op| match op {
+                Operation::Install(p) => format!("install {} {}", op.name(), p.version),
+                Operation::Update(from, to) => format!("update {} {} {}", op.name(), from.version, to.version),
+                Operation::Uninstall(p) => format!("remove {} {}", op.name(), p.version),
+            })
+            .collect();
+        assert_eq!(summary, ["remove c/removed 1.0.0", "update b/updated 1.0.0 1.1.0", "install d/new 2.0.0"]);
+    }
+
+    #[test]
+    fn leaves_dev_packages_out_without_dev() {
+        let packages = || vec![package("a/app", "1.0.0"), package("c/shared", "1.0.0")];
+        let packages_dev = || vec![package("b/phpunit", "9.0.0")];
+        let names = |packages: &[PackageVersion]| -> Vec<String> { packages.iter().filter_map(|p| p.name.clone()).collect() };
+
+        let (selected, dev_names) = select(packages(), packages_dev(), false);
+        assert_eq!(names(&selected), ["a/app", "c/shared"]);
+        let state = Installed::new(&selected, dev_names, false);
+        assert!(state.dev_package_names.is_empty());
+        assert!(state.packages.iter().all(|entry| entry.package.name.as_deref() != Some("b/phpunit")));
+
+        let (selected, dev_names) = select(packages(), packages_dev(), true);
+        assert_eq!(names(&selected), ["a/app", "c/shared", "b/phpunit"]);
+        assert_eq!(dev_names, ["b/phpunit"]);
+    }
+}
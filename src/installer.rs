@@ -1,39 +1,157 @@
 use std::fs;
-use std::io::Cursor;
-use std::path::Path;
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
 use anyhow::{Context, Result};
+use bzip2::read::BzDecoder;
+use flate2::read::GzDecoder;
+use xz2::read::XzDecoder;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use crate::auth::{self, Auth};
 use crate::cache::Cache;
+use crate::registry::{DistInfo, SourceInfo};
 
-pub async fn install_package(name: &str, version: &str, url: &str) -> Result<()> {
-    let cache = Cache::new();
-    let cache_path = cache.get_dist_path(name, version);
+// Packages are put together under vendor/.maestro/staging and only moved into vendor/ once every
+// one of them succeeded, so a failed download or an interrupted run leaves vendor/ as it was.
+#[derive(Clone)]
+pub struct Staging {
+    vendor: PathBuf,
+    staging: PathBuf,
+    // what commit() replaced, until all moves went through
+    backup: PathBuf,
+}
 
-    let bytes: Vec<u8>;
+impl Staging {
+    pub fn begin(vendor: &Path) -> Result<Self> {
+        let root = vendor.join(".maestro");
+        let staging = Self { vendor: vendor.to_path_buf(), staging: root.join("staging"), backup: root.join("backup") };
+        staging.recover()?;
+        if staging.staging.exists() {
+            fs::remove_dir_all(&staging.staging).context("Failed to clean the staging directory")?;
+        }
+        fs::create_dir_all(&staging.staging).context("Failed to create the staging directory")?;
+        Ok(staging)
+    }
 
-    if cache_path.exists() {
-        bytes = fs::read(&cache_path)?;
-    } else {
-        let client = reqwest::Client::builder()
-            .user_agent("Maestro/0.1")
-            .build()?;
+    fn path(&self, name: &str) -> PathBuf {
+        self.staging.join(name)
+    }
+
+    // the directory vendor/<name> will be in, for links relative to it
+    fn final_parent(&self, name: &str) -> Result<PathBuf> {
+        let target = fs::canonicalize(&self.vendor)?.join(name);
+        Ok(target.parent().map(Path::to_path_buf).unwrap_or(target))
+    }
+
+    // Move the staged packages into vendor/ and take the removed ones out. If one of the moves fails,
+    // the ones already made are undone and the replaced or removed packages put back.
+    pub fn commit(self, names: &[String], removals: &[String]) -> Result<()> {
+        let moves = names.iter().map(|name| (name, true)).chain(removals.iter().map(|name| (name, false)));
+        let mut moved = Vec::new();
+        for (name, staged) in moves {
+            match self.swap(name, staged) {
+                Ok(()) => moved.push(name),
+                Err(e) => {
+                    for name in moved.into_iter().rev() {
+                        self.restore(name);
+                    }
+                    self.discard();
+                    return Err(e).with_context(|| format!("Failed to move {} into place, vendor/ was restored", name));
+                }
+            }
+        }
+        self.discard();
+
+        // vendor/<vendor> directories that held nothing but removed packages
+        for name in removals {
+            if let Some(parent) = self.vendor.join(name).parent() {
+                fs::remove_dir(parent).ok();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn abort(self) {
+        self.discard();
+    }
+
+    // move vendor/<name> aside, and the staged package (if any) in its place
+    fn swap(&self, name: &str, staged: bool) -> Result<()> {
+        let target = self.vendor.join(name);
+        let backup = self.backup.join(name);
+        let replaced = fs::symlink_metadata(&target).is_ok();
+        if replaced {
+            fs::create_dir_all(backup.parent().unwrap_or(&self.backup))?;
+            fs::rename(&target, &backup)?;
+        }
+        if !staged {
+            return Ok(());
+        }
+        fs::create_dir_all(target.parent().unwrap_or(&self.vendor))?;
+        if let Err(e) = fs::rename(self.path(name), &target) {
+            if replaced {
+                fs::rename(&backup, &target).ok();
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn restore(&self, name: &str) {
+        let target = self.vendor.join(name);
+        remove_entry(&target).ok();
+        let backup = self.backup.join(name);
+        if fs::symlink_metadata(&backup).is_ok() {
+            fs::rename(&backup, &target).ok();
+        }
+    }
+
+    // A commit cut short (power loss, kill -9) leaves replaced and removed packages in the backup.
+    // installed.json is only written after a commit, so it still describes the old vendor/: every
+    // backup goes back in place, over the new version if that was already moved in.
+    fn recover(&self) -> Result<()> {
+        let Ok(vendors) = fs::read_dir(&self.backup) else {
+            return Ok(());
+        };
+        for vendor in vendors.flatten() {
+            for package in fs::read_dir(vendor.path())?.flatten() {
+                let target = self.vendor.join(vendor.file_name()).join(package.file_name());
+                if fs::symlink_metadata(&target).is_ok() {
+                    remove_entry(&target).with_context(|| format!("Failed to restore {}", target.display()))?;
+                }
+                fs::create_dir_all(target.parent().unwrap_or(&self.vendor))?;
+                fs::rename(package.path(), &target)?;
+            }
+        }
+        fs::remove_dir_all(&self.backup).context("Failed to clean the backup directory")
+    }
 
-        let response = client.get(url).send().await?;
-        if !response.status().is_success() {
-            anyhow::bail!("Download failed: {}", response.status());
+    fn discard(&self) {
+        if let Some(root) = self.staging.parent() {
+            fs::remove_dir_all(root).ok();
         }
-        bytes = response.bytes().await?.to_vec();
+    }
+}
 
-        // save to cache
-        let _ = fs::write(&cache_path, &bytes);
+pub async fn install_package(name: &str, dist: &DistInfo, symlink: Option<bool>, auth: &Auth, staging: &Staging) -> Result<()> {
+    let path = staging.path(name);
+    if dist.r#type == "path" {
+        return install_path(Path::new(&dist.url), &path, &staging.final_parent(name)?, symlink);
     }
 
+    let bytes = download(name, dist, auth).await?;
+
     // extract
-    let install_dir = format!("vendor/{}", name);
-    let path = Path::new(&install_dir);
-    if path.exists() {
-        fs::remove_dir_all(path).context("Failed to clean existing directory")?;
+    fs::create_dir_all(&path).context("Failed to create vendor directory")?;
+    match dist.r#type.as_str() {
+        "zip" => extract_zip(bytes, &path),
+        "tar" | "tar.gz" | "tgz" | "tar.bz2" | "tbz2" | "xz" | "tar.xz" | "txz" => extract_tar(&bytes, &path),
+        other => anyhow::bail!("Unsupported dist type \"{}\" for {}", other, name),
     }
-    fs::create_dir_all(path).context("Failed to create vendor directory")?;
+}
+
+fn extract_zip(bytes: Vec<u8>, path: &Path) -> Result<()> {
     let cursor = Cursor::new(bytes); // Wrap bytes so zip can read them
     let mut archive = zip::ZipArchive::new(cursor).context("Failed to read zip archive")?;
 
@@ -41,7 +159,7 @@ pub async fn install_package(name: &str, version: &str, url: &str) -> Result<()>
         let mut file = archive.by_index(i)?;
 
         let filepath = match file.enclosed_name() {
-            Some(path) => path,
+            Some(path) => path.to_owned(),
             None => continue,
         };
 
@@ -50,26 +168,561 @@ pub async fn install_package(name: &str, version: &str, url: &str) -> Result<()>
             continue;
         }
 
-        // Strip the first directory component
-        let mut components = filepath.components();
-        components.next(); // Skip root
-        let relative_path = components.as_path();
+        let Some(relative_path) = strip_root(&filepath) else {
+            continue;
+        };
+        let outpath = path.join(relative_path);
+
+        if let Some(p) = outpath.parent()
+            && !p.exists()
+        {
+            fs::create_dir_all(p)?;
+        }
+
+        let mut outfile = fs::File::create(&outpath)?;
+        std::io::copy(&mut file, &mut outfile)?;
+    }
+
+    Ok(())
+}
+
+// "tar" dists may be compressed whatever their declared type says (composer lets PharData
+// sniff it), so the compression is picked from the magic bytes
+fn extract_tar(bytes: &[u8], path: &Path) -> Result<()> {
+    let reader: Box<dyn Read> = if bytes.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(bytes))
+    } else if bytes.starts_with(b"BZh") {
+        Box::new(BzDecoder::new(bytes))
+    } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+        Box::new(XzDecoder::new(bytes))
+    } else {
+        Box::new(bytes)
+    };
+    let mut archive = tar::Archive::new(reader);
 
-        if relative_path.as_os_str().is_empty() {
+    // Symlinks are made last, once every file is in place: nothing is ever written through one,
+    // so an entry like "root/x -> /etc" followed by "root/x/passwd" can't reach outside `path`.
+    let mut symlinks: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for entry in archive.entries().context("Failed to read tar archive")? {
+        let mut entry = entry.context("Failed to read tar archive")?;
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
             continue;
         }
+        // also drops github's pax_global_header, which sits next to the root folder
+        let Some(relative_path) = strip_root(&entry.path()?) else {
+            continue;
+        };
+        let outpath = path.join(&relative_path);
 
-        let outpath = path.join(relative_path);
+        if let Some(p) = outpath.parent()
+            && !p.exists()
+        {
+            fs::create_dir_all(p)?;
+        }
 
-        if let Some(p) = outpath.parent() {
-            if !p.exists() {
-                fs::create_dir_all(p)?;
+        if entry_type.is_symlink() {
+            let target = entry.link_name()?.context("Symlink without a target in tar archive")?.into_owned();
+            if !link_stays_inside(&relative_path, &target) {
+                anyhow::bail!("{} links to {}, outside the package", relative_path.display(), target.display());
+            }
+            symlinks.push((outpath, target));
+        } else if entry_type.is_hard_link() {
+            // hard link targets are archive paths, so they go through the same root stripping
+            let target = entry.link_name()?.context("Hard link without a target in tar archive")?.into_owned();
+            let source = strip_root(&target).map(|source| path.join(source))
+                .filter(|source| fs::symlink_metadata(source).is_ok_and(|meta| meta.is_file()));
+            let Some(source) = source else {
+                anyhow::bail!("{} links to {}, outside the package", relative_path.display(), target.display());
+            };
+            if fs::symlink_metadata(&outpath).is_ok() {
+                remove_entry(&outpath)?;
             }
+            fs::hard_link(&source, &outpath)?;
+        } else if entry_type.is_file() || entry_type.is_contiguous() {
+            // unpack keeps the mode, so bin scripts stay executable
+            entry.unpack(&outpath)?;
         }
+    }
 
-        let mut outfile = fs::File::create(&outpath)?;
-        std::io::copy(&mut file, &mut outfile)?;
+    for (link, target) in &symlinks {
+        if fs::symlink_metadata(link).is_ok() {
+            remove_entry(link)?;
+        }
+        make_symlink(target, link)?;
+    }
+    // one link can lead through another ("x -> ." and "l -> x/y/../.."): check where they end up
+    let root = fs::canonicalize(path)?;
+    for (link, target) in &symlinks {
+        if let Ok(resolved) = fs::canonicalize(link)
+            && !resolved.starts_with(&root)
+        {
+            anyhow::bail!("{} links to {}, outside the package", link.display(), target.display());
+        }
+    }
+    Ok(())
+}
+
+// a relative symlink target that, seen from the link's directory, stays within the package
+fn link_stays_inside(link: &Path, target: &Path) -> bool {
+    let mut depth = link.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
     }
+    true
+}
 
+// Strip the first directory component: "vendor-package-abc123/src/Foo.php" -> "src/Foo.php".
+// None for the root itself and for entries escaping the archive ("../", absolute paths).
+fn strip_root(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components().filter(|c| *c != Component::CurDir);
+    if !matches!(components.next()?, Component::Normal(_)) {
+        return None;
+    }
+    let mut relative = PathBuf::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            _ => return None,
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+// Install from source: clone the repository into vendor/<name> and check out the locked commit.
+// Dev versions get a local branch, so changes can be committed and pushed from vendor/.
+// git runs on the blocking pool, so a slow clone doesn't hold up the runtime (or Ctrl-C handling).
+pub async fn install_source(name: &str, version: &str, source: &SourceInfo, staging: &Staging) -> Result<()> {
+    let (source, version, target) = (source.clone(), version.to_string(), staging.path(name));
+    tokio::task::spawn_blocking(move || checkout(&source, &version, &target)).await?
+}
+
+fn checkout(source: &SourceInfo, version: &str, target: &Path) -> Result<()> {
+    if source.r#type != "git" {
+        anyhow::bail!("Unsupported source type \"{}\"", source.r#type);
+    }
+    let reference = source.reference.as_deref()
+        .filter(|reference| !reference.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("No reference to check out from {}", source.url))?;
+
+    if fs::symlink_metadata(target).is_ok() {
+        remove_entry(target).context("Failed to clean existing directory")?;
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).context("Failed to create vendor directory")?;
+    }
+
+    // objects already fetched into the vcs repository cache don't need to be downloaded again
+    let mut clone = Command::new("git");
+    clone.args(["clone", "--no-checkout", "--quiet"]);
+    let mirror = Cache::new().get_vcs_path(&source.url);
+    if mirror.exists() {
+        clone.arg("--reference-if-able").arg(&mirror).arg("--dissociate");
+    }
+    git(clone.arg(&source.url).arg(target))
+        .with_context(|| format!("Failed to clone {}", source.url))?;
+
+    let mut checkout = Command::new("git");
+    checkout.arg("-C").arg(target).args(["checkout", "--quiet"]);
+    if let Some(branch) = version.strip_prefix("dev-") {
+        checkout.args(["-B", branch]);
+    }
+    git(checkout.arg(reference))
+        .with_context(|| format!("Failed to check out {} from {}", reference, source.url))
+}
+
+fn git(command: &mut Command) -> Result<()> {
+    let output = command.output().context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+// The dist archive of a package, checked against the sha256 recorded in composer.lock or the
+// registry's sha1 shasum. Cached archives that fail the check are evicted and downloaded again.
+pub async fn download(name: &str, dist: &DistInfo, auth: &Auth) -> Result<Vec<u8>> {
+    let url = dist.url.as_str();
+    if !url.contains("://") || url.starts_with("file://") {
+        // artifact repositories point at zip files on disk
+        let file = url.strip_prefix("file://").unwrap_or(url);
+        let bytes = fs::read(file).with_context(|| format!("Failed to read {}", file))?;
+        verify(&bytes, dist, file)?;
+        return Ok(bytes);
+    }
+
+    let cache_path = Cache::new().get_dist_path(name, dist.reference.as_deref(), url, &dist.r#type);
+    if let Some(bytes) = read_cached(&cache_path, dist, url) {
+        return Ok(bytes);
+    }
+
+    let client = reqwest::Client::builder()
+        .user_agent("Maestro/0.1")
+        .redirect(auth::redirect_policy())
+        .build()?;
+
+    let response = auth.get(&client, url, reqwest::header::HeaderMap::new()).await?;
+    if !response.status().is_success() {
+        anyhow::bail!("Download failed: {}", response.status());
+    }
+    let bytes = response.bytes().await?.to_vec();
+    verify(&bytes, dist, url)?;
+
+    // save to cache
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let _ = fs::write(&cache_path, &bytes);
+    Ok(bytes)
+}
+
+// a cached archive that no longer matches its checksum is removed, so it gets downloaded again
+fn read_cached(cache_path: &Path, dist: &DistInfo, url: &str) -> Option<Vec<u8>> {
+    let bytes = fs::read(cache_path).ok()?;
+    if verify(&bytes, dist, url).is_ok() {
+        return Some(bytes);
+    }
+    fs::remove_file(cache_path).ok();
+    None
+}
+
+pub fn sha256(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+// sha256 wins when both are known; an empty shasum (github zipballs) means there is nothing to check
+fn verify(bytes: &[u8], dist: &DistInfo, origin: &str) -> Result<()> {
+    let (expected, actual) = match (&dist.sha256, &dist.shasum) {
+        (Some(expected), _) if !expected.is_empty() => (expected, sha256(bytes)),
+        (_, Some(expected)) if !expected.is_empty() => (expected, hex::encode(Sha1::digest(bytes))),
+        _ => return Ok(()),
+    };
+    if !expected.eq_ignore_ascii_case(&actual) {
+        anyhow::bail!("The checksum verification of {} failed: expected {}, got {}", origin, expected, actual);
+    }
+    Ok(())
+}
+
+// Path repositories: link vendor/<name> to the package's directory (relative to `link_from`, where
+// the link will finally live, like composer), falling back to a copy where links aren't possible.
+// `symlink: false` always copies.
+fn install_path(source: &Path, target: &Path, link_from: &Path, symlink: Option<bool>) -> Result<()> {
+    let source = fs::canonicalize(source).with_context(|| format!("Path {} not found", source.display()))?;
+    let parent = target.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent).context("Failed to create vendor directory")?;
+
+    if symlink != Some(false) {
+        let link = relative_path(link_from, &source);
+        match make_symlink(&link, target) {
+            Ok(()) => return Ok(()),
+            Err(e) if symlink == Some(true) => {
+                return Err(e).with_context(|| format!("Failed to link {}", target.display()));
+            }
+            Err(_) => {}
+        }
+    }
+    copy_dir(&source, target)
+}
+
+fn remove_entry(path: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(unix)]
+fn make_symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn make_symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(original, link)
+}
+
+// `to` as seen from the directory `from` (both absolute)
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for component in &to[common..] {
+        path.push(component);
+    }
+    path
+}
+
+fn copy_dir(source: &Path, target: &Path) -> Result<()> {
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let destination = target.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &destination)?;
+        } else {
+            fs::copy(entry.path(), &destination)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git").arg("-C").arg(dir)
+            .args(["-c", "user.name=Maestro", "-c", "user.email=maestro@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[test]
+    fn checks_out_source_from_bare_repository() {
+        let root = std::env::temp_dir().join(format!("maestro-source-{}", std::process::id()));
+        let work = root.join("work");
+        fs::create_dir_all(&work).unwrap();
+        run(&work, &["init", "--quiet", "--initial-branch=main"]);
+        fs::write(work.join("composer.json"), r#"{"name": "acme/lib"}"#).unwrap();
+        run(&work, &["add", "."]);
+        run(&work, &["commit", "--quiet", "-m", "first"]);
+        let first = run(&work, &["rev-parse", "HEAD"]);
+        fs::write(work.join("README"), "second").unwrap();
+        run(&work, &["add", "."]);
+        run(&work, &["commit", "--quiet", "-m", "second"]);
+
+        let bare = root.join("remote.git");
+        run(&root, &["clone", "--quiet", "--bare", work.to_str().unwrap(), bare.to_str().unwrap()]);
+        let source = |reference: &str| SourceInfo {
+            url: bare.to_string_lossy().into_owned(),
+            r#type: "git".into(),
+            reference: Some(reference.into()),
+            mirrors: None,
+        };
+
+        // a tag: detached at the locked commit, even though the branch moved on
+        let target = root.join("vendor/acme/lib");
+        checkout(&source(&first), "1.0.0", &target).unwrap();
+        assert_eq!(run(&target, &["rev-parse", "HEAD"]), first);
+        assert!(!target.join("README").exists());
+
+        // a branch: checked out on a local branch named after it
+        checkout(&source(&first), "dev-main", &target).unwrap();
+        assert_eq!(run(&target, &["rev-parse", "--abbrev-ref", "HEAD"]), "main");
+        assert_eq!(run(&target, &["rev-parse", "HEAD"]), first);
+
+        assert!(checkout(&source("0000000000000000000000000000000000000000"), "1.0.0", &target).is_err());
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn commit_swaps_staged_packages_or_restores_vendor() {
+        let vendor = std::env::temp_dir().join(format!("maestro-staging-{}", std::process::id())).join("vendor");
+        fs::create_dir_all(vendor.join("acme/lib")).unwrap();
+        fs::write(vendor.join("acme/lib/old"), "").unwrap();
+
+        // acme/missing was never staged, so its move fails and acme/lib must be put back
+        let staging = Staging::begin(&vendor).unwrap();
+        fs::create_dir_all(staging.path("acme/lib")).unwrap();
+        fs::write(staging.path("acme/lib/new"), "").unwrap();
+        assert!(staging.commit(&["acme/lib".into(), "acme/missing".into()], &[]).is_err());
+        assert!(vendor.join("acme/lib/old").exists());
+        assert!(!vendor.join("acme/lib/new").exists());
+        assert!(!vendor.join(".maestro").exists());
+
+        let staging = Staging::begin(&vendor).unwrap();
+        fs::create_dir_all(staging.path("acme/lib")).unwrap();
+        fs::write(staging.path("acme/lib/new"), "").unwrap();
+        staging.commit(&["acme/lib".into()], &[]).unwrap();
+        assert!(vendor.join("acme/lib/new").exists());
+        assert!(!vendor.join("acme/lib/old").exists());
+        assert!(!vendor.join(".maestro").exists());
+
+        // a commit killed halfway: acme/lib was already swapped, acme/gone already taken out;
+        // the next run puts both back the way installed.json still describes them
+        fs::create_dir_all(vendor.join(".maestro/backup/acme/lib")).unwrap();
+        fs::write(vendor.join(".maestro/backup/acme/lib/old"), "").unwrap();
+        fs::create_dir_all(vendor.join(".maestro/backup/acme/gone")).unwrap();
+        Staging::begin(&vendor).unwrap().abort();
+        assert!(vendor.join("acme/gone").is_dir());
+        assert!(vendor.join("acme/lib/old").exists());
+        assert!(!vendor.join("acme/lib/new").exists());
+
+        fs::remove_dir_all(vendor.parent().unwrap()).ok();
+    }
+
+    enum TarEntry<'a> {
+        File(&'a str, &'a [u8], u32),
+        Symlink(&'a str, &'a str),
+        HardLink(&'a str, &'a str),
+    }
+
+    fn tarball(entries: &[TarEntry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            match entry {
+                TarEntry::File(name, content, mode) => {
+                    header.set_size(content.len() as u64);
+                    header.set_mode(*mode);
+                    header.set_cksum();
+                    builder.append_data(&mut header, name, *content).unwrap();
+                }
+                TarEntry::Symlink(name, target) | TarEntry::HardLink(name, target) => {
+                    let kind = if matches!(entry, TarEntry::Symlink(..)) { tar::EntryType::Symlink } else { tar::EntryType::Link };
+                    header.set_entry_type(kind);
+                    header.set_size(0);
+                    header.set_mode(0o777);
+                    builder.append_link(&mut header, name, target).unwrap();
+                }
+            }
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn compress(tar: &[u8], format: &str) -> Vec<u8> {
+        use std::io::Write;
+        match format {
+            "tar" => tar.to_vec(),
+            "tar.gz" => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(tar).unwrap();
+                encoder.finish().unwrap()
+            }
+            "tar.bz2" => {
+                let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(tar).unwrap();
+                encoder.finish().unwrap()
+            }
+            "tar.xz" => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(tar).unwrap();
+                encoder.finish().unwrap()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("maestro-tar-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn extracts_tar_in_every_compression() {
+        #[cfg(unix)]
+        use std::os::unix::fs::PermissionsExt;
+        let tar = tarball(&[
+            TarEntry::File("acme-lib-1a2b3c/src/Lib.php", b"<?php // lib", 0o644),
+            TarEntry::File("acme-lib-1a2b3c/bin/tool", b"#!/usr/bin/env php", 0o755),
+            TarEntry::Symlink("acme-lib-1a2b3c/bin/lib.php", "../src/Lib.php"),
+            TarEntry::HardLink("acme-lib-1a2b3c/src/Copy.php", "acme-lib-1a2b3c/src/Lib.php"),
+        ]);
+
+        for format in ["tar", "tar.gz", "tar.bz2", "tar.xz"] {
+            let dir = scratch(format);
+            extract_tar(&compress(&tar, format), &dir).unwrap();
+            assert_eq!(fs::read_to_string(dir.join("src/Lib.php")).unwrap(), "<?php // lib", "{}", format);
+            #[cfg(unix)]
+            assert_eq!(fs::metadata(dir.join("bin/tool")).unwrap().permissions().mode() & 0o777, 0o755, "{}", format);
+            assert_eq!(fs::read_link(dir.join("bin/lib.php")).unwrap(), Path::new("../src/Lib.php"), "{}", format);
+            assert_eq!(fs::read_to_string(dir.join("bin/lib.php")).unwrap(), "<?php // lib", "{}", format);
+            assert_eq!(fs::read_to_string(dir.join("src/Copy.php")).unwrap(), "<?php // lib", "{}", format);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_tar_links_leaving_the_package() {
+        let outside = scratch("outside");
+        let outside_str = outside.to_str().unwrap();
+        let cases: [(&str, Vec<TarEntry>); 4] = [
+            ("absolute symlink, then a file through it", vec![
+                TarEntry::Symlink("root/x", outside_str),
+                TarEntry::File("root/x/passwd", b"owned", 0o644),
+            ]),
+            ("relative symlink climbing out", vec![TarEntry::Symlink("root/sub/up", "../../..")]),
+            ("hard link to an absolute path", vec![TarEntry::HardLink("root/passwd", "/etc/passwd")]),
+            ("symlink chained through another", vec![
+                TarEntry::File("root/y/f", b"", 0o644),
+                TarEntry::Symlink("root/x", "."),
+                TarEntry::Symlink("root/l", "x/y/../.."),
+            ]),
+        ];
+
+        for (i, (case, entries)) in cases.iter().enumerate() {
+            let dir = scratch(&format!("evil-{}", i));
+            let result = extract_tar(&tarball(entries), &dir);
+            assert!(result.is_err_and(|e| e.to_string().contains("outside the package")), "{}", case);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+        fs::remove_dir_all(&outside).unwrap();
+    }
+
+    fn dist(sha256: Option<&str>, shasum: Option<&str>) -> DistInfo {
+        DistInfo {
+            r#type: "zip".into(),
+            url: "https://example.com/a.zip".into(),
+            reference: Some("abc".into()),
+            shasum: shasum.map(String::from),
+            sha256: sha256.map(String::from),
+            mirrors: None,
+        }
+    }
+
+    #[test]
+    fn verifies_dist_checksums() {
+        let bytes = b"archive";
+        let good_sha256 = sha256(bytes);
+        let good_sha1 = hex::encode(Sha1::digest(bytes));
+
+        assert!(verify(bytes, &dist(Some(&good_sha256), None), "a.zip").is_ok());
+        assert!(verify(bytes, &dist(Some(&good_sha256.to_uppercase()), None), "a.zip").is_ok());
+        assert!(verify(bytes, &dist(None, Some(&good_sha1)), "a.zip").is_ok());
+        // sha256 wins over a stale shasum, an empty one checks nothing
+        assert!(verify(bytes, &dist(Some(&good_sha256), Some("0000")), "a.zip").is_ok());
+        assert!(verify(bytes, &dist(None, Some("")), "a.zip").is_ok());
+        assert!(verify(bytes, &dist(None, None), "a.zip").is_ok());
+
+        let error = verify(bytes, &dist(Some("0000"), Some(&good_sha1)), "a.zip").unwrap_err().to_string();
+        assert_eq!(error, format!("The checksum verification of a.zip failed: expected 0000, got {}", good_sha256));
+        assert!(verify(bytes, &dist(None, Some("0000")), "a.zip").is_err());
+    }
+
+    #[test]
+    fn evicts_cached_archives_failing_the_check() {
+        let dir = scratch("cache");
+        let cached = dir.join("abc.zip");
+        fs::write(&cached, b"archive").unwrap();
+
+        let expected = dist(Some(&sha256(b"archive")), None);
+        assert_eq!(read_cached(&cached, &expected, "a.zip").as_deref(), Some(&b"archive"[..]));
+        assert!(cached.exists());
+
+        let tampered = dist(Some(&sha256(b"other")), None);
+        assert!(read_cached(&cached, &tampered, "a.zip").is_none());
+        assert!(!cached.exists());
+        assert!(read_cached(&cached, &expected, "a.zip").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
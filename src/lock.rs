@@ -1,38 +1,403 @@
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use indexmap::IndexMap;
 use std::fs;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use md5::{Digest, Md5};
+use serde_json::Value;
+use crate::manifest::ComposerManifest;
 use crate::registry::PackageVersion;
+use crate::solver::is_platform_package;
+use crate::version::normalize;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LockFile {
     #[serde(rename = "_readme")]
     pub _readme: Vec<String>,
     #[serde(rename = "content-hash")]
-    pub content_hash: String, // @todo
+    pub content_hash: String,
+    #[serde(serialize_with = "serialize_locked")]
     pub packages: Vec<PackageVersion>,
-    #[serde(rename = "packages-dev", default)]
-    pub packages_dev: Vec<PackageVersion>, // Placeholder
+    #[serde(rename = "packages-dev", default, serialize_with = "serialize_locked")]
+    pub packages_dev: Vec<PackageVersion>,
+    // inline aliases from the root requirements ("dev-main as 1.0.x-dev")
+    #[serde(default)]
+    pub aliases: Vec<Alias>,
+    #[serde(rename = "minimum-stability", default = "default_minimum_stability")]
+    pub minimum_stability: String,
+    // composer's numeric stability priorities (stable = 0 ... dev = 20)
+    #[serde(rename = "stability-flags", default, deserialize_with = "deserialize_stability_flags")]
+    pub stability_flags: BTreeMap<String, u8>,
+    #[serde(rename = "prefer-stable", default)]
+    pub prefer_stable: bool,
+    #[serde(rename = "prefer-lowest", default)]
+    pub prefer_lowest: bool,
+    // the root package's own php/ext-* requirements, in composer.json order
+    #[serde(default, deserialize_with = "deserialize_links")]
+    pub platform: IndexMap<String, String>,
+    #[serde(rename = "platform-dev", default, deserialize_with = "deserialize_links")]
+    pub platform_dev: IndexMap<String, String>,
+    #[serde(rename = "platform-overrides", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub platform_overrides: BTreeMap<String, serde_json::Value>,
+    #[serde(rename = "plugin-api-version", default, skip_serializing_if = "Option::is_none")]
+    pub plugin_api_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alias {
+    pub package: String,
+    pub version: String,
+    pub alias: String,
+    pub alias_normalized: String,
+}
+
+// the plugin api of the composer version whose lock format we write
+const PLUGIN_API_VERSION: &str = "2.6.0";
+
+fn default_minimum_stability() -> String {
+    "stable".into()
+}
+
+// composer writes empty link maps as []
+fn deserialize_links<'de, D>(deserializer: D) -> Result<IndexMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>, {
+    let v: Value = Deserialize::deserialize(deserializer)?;
+    Ok(v.as_object().into_iter().flatten()
+        .filter_map(|(name, constraint)| Some((name.clone(), constraint.as_str()?.to_string())))
+        .collect())
+}
+
+// composer leaves version_normalized out of the lock; load() puts it back
+fn serialize_locked<S>(packages: &[PackageVersion], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer, {
+    let mut seq = serializer.serialize_seq(Some(packages.len()))?;
+    for package in packages {
+        let mut value = serde_json::to_value(package).map_err(serde::ser::Error::custom)?;
+        if let Some(object) = value.as_object_mut() {
+            object.shift_remove("version_normalized");
+        }
+        seq.serialize_element(&value)?;
+    }
+    seq.end()
+}
+
+// older composer versions write an empty {} as []
+fn deserialize_stability_flags<'de, D>(deserializer: D) -> Result<BTreeMap<String, u8>, D::Error>
+where
+    D: Deserializer<'de>, {
+    let v: Value = Deserialize::deserialize(deserializer)?;
+    Ok(v.as_object().into_iter().flatten()
+        .filter_map(|(name, priority)| Some((name.clone(), u8::try_from(priority.as_u64()?).ok()?)))
+        .collect())
+}
+
+fn platform_links(links: &IndexMap<String, String>) -> IndexMap<String, String> {
+    links.iter()
+        .filter(|(name, _)| is_platform_package(name))
+        .map(|(name, constraint)| (name.clone(), constraint.clone()))
+        .collect()
 }
 
 impl LockFile {
-    pub fn new(packages: Vec<PackageVersion>) -> Self {
+    pub fn new(mut packages: Vec<PackageVersion>, mut packages_dev: Vec<PackageVersion>, manifest: &ComposerManifest, content_hash: String) -> Self {
+        // resolution order depends on which download finished first; composer sorts by name
+        sort_packages(&mut packages);
+        sort_packages(&mut packages_dev);
         Self {
-            _readme: vec!["This file locks the dependencies of your project to a known state".into()],
-            content_hash: "TODO-hash-of-composer-json".into(),
+            _readme: vec![
+                "This file locks the dependencies of your project to a known state".into(),
+                "Read more about it at https://getcomposer.org/doc/01-basic-usage.md#installing-dependencies".into(),
+                "This file is @generated automatically".into(),
+            ],
+            content_hash,
             packages,
-            packages_dev: vec![],
+            packages_dev,
+            aliases: Vec::new(),
+            minimum_stability: manifest.minimum_stability().as_str().into(),
+            stability_flags: manifest.stability_flags().into_iter()
+                .map(|(name, stability)| (name, stability.priority()))
+                .collect(),
+            prefer_stable: manifest.prefer_stable(),
+            prefer_lowest: false,
+            platform: platform_links(&manifest.require),
+            platform_dev: platform_links(&manifest.require_dev),
+            platform_overrides: manifest.config.platform.clone(),
+            plugin_api_version: Some(PLUGIN_API_VERSION.into()),
         }
     }
 
     pub fn load(path: &str) -> Result<Self> {
         let content = fs::read_to_string(path)?;
-        let lock: Self = serde_json::from_str(&content)?;
+        let mut lock: Self = serde_json::from_str(&content)?;
+        for package in lock.packages.iter_mut().chain(lock.packages_dev.iter_mut()) {
+            if package.version_normalized.is_empty() {
+                package.version_normalized = normalize(&package.version)
+                    .with_context(|| format!("Invalid version \"{}\" in {}", package.version, path))?;
+            }
+        }
         Ok(lock)
     }
 
     pub fn save(&self, path: &str) -> Result<()> {
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(path, content)?;
+        fs::write(path, to_json(self)?)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn sort_packages(packages: &mut [PackageVersion]) {
+    packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+}
+
+// JSON the way composer writes its files: 4-space indent, slashes and unicode unescaped, and a
+// trailing newline
+pub fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    let mut out = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, serde_json::ser::PrettyFormatter::with_indent(b"    "));
+    value.serialize(&mut serializer)?;
+    out.push(b'\n');
+    Ok(String::from_utf8(out)?)
+}
+
+// The keys of composer.json that affect resolution, as in composer's Locker::getContentHash()
+const RELEVANT_KEYS: [&str; 11] = [
+    "name", "version", "require", "require-dev", "conflict", "replace", "provide",
+    "minimum-stability", "prefer-stable", "repositories", "extra",
+];
+
+// md5 over the relevant keys of composer.json (sorted; nested values keep their order), encoded like
+// php's json_encode() does, so the hash matches the one composer writes and checks
+pub fn content_hash(composer_json: &str) -> Result<String> {
+    let content: Value = serde_json::from_str(composer_json).context("composer.json is not valid JSON")?;
+    let Some(content) = content.as_object() else {
+        anyhow::bail!("composer.json does not contain a JSON object");
+    };
+
+    let mut relevant: BTreeMap<String, Value> = RELEVANT_KEYS.iter()
+        .filter_map(|key| Some((key.to_string(), content.get(*key)?.clone())))
+        .collect();
+    if let Some(platform) = content.get("config").and_then(|config| config.get("platform"))
+        && !platform.is_null()
+    {
+        relevant.insert("config".into(), serde_json::json!({ "platform": platform }));
+    }
+
+    let mut encoded = String::new();
+    php_json_encode(&Value::Object(relevant.into_iter().collect()), &mut encoded);
+    Ok(hex::encode(Md5::digest(encoded.as_bytes())))
+}
+
+// json_encode() without flags: "/" and non-ascii characters escaped, and objects that php decodes
+// into plain lists ({} and {"0": .., "1": ..}) written as lists
+fn php_json_encode(value: &Value, out: &mut String) {
+    match value {
+        Value::String(s) => php_json_string(s, out),
+        Value::Array(items) => php_json_list(items.iter(), out),
+        Value::Object(map) if map.keys().enumerate().all(|(i, key)| *key == i.to_string()) => {
+            php_json_list(map.values(), out)
+        }
+        Value::Object(map) => {
+            out.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                php_json_string(key, out);
+                out.push(':');
+                php_json_encode(value, out);
+            }
+            out.push('}');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+fn php_json_list<'a>(items: impl Iterator<Item = &'a Value>, out: &mut String) {
+    out.push('[');
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        php_json_encode(item, out);
+    }
+    out.push(']');
+}
+
+fn php_json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '/' => out.push_str("\\/"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c if c.is_ascii() => out.push(c),
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    out.push('"');
+}
+
+// Keep the sha256 recorded for packages whose dist reference did not change, so an update only
+// downloads what is new and a checksum stays the one of the first download.
+pub fn keep_checksums(packages: &mut [PackageVersion], locked: &[PackageVersion]) {
+    for package in packages {
+        let Some(dist) = &mut package.dist else { continue };
+        if dist.sha256.is_some() || dist.reference.is_none() {
+            continue;
+        }
+        let previous = locked.iter()
+            .filter(|p| p.name == package.name)
+            .find_map(|p| p.dist.as_ref())
+            .filter(|previous| previous.reference == dist.reference && previous.url == dist.url);
+        if let Some(previous) = previous {
+            dist.sha256 = previous.sha256.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::DistInfo;
+
+    fn encode(json: &str) -> String {
+        let mut out = String::new();
+        php_json_encode(&serde_json::from_str(json).unwrap(), &mut out);
+        out
+    }
+
+    #[test]
+    fn encodes_like_php_json_encode() {
+        assert_eq!(encode(r#"{"b": {"z/z": "^1.0", "a/a": "*"}, "a": []}"#), r#"{"b":{"z\/z":"^1.0","a\/a":"*"},"a":[]}"#);
+        assert_eq!(encode(r#"{"require-dev": {}, "list": {"0": "x", "1": "y"}}"#), r#"{"require-dev":[],"list":["x","y"]}"#);
+        assert_eq!(encode(r#"["Jérôme \"J\" 🎵", "tab\t\u0001"]"#), r#"["J\u00e9r\u00f4me \"J\" \ud83c\udfb5","tab\t\u0001"]"#);
+    }
+
+    #[test]
+    fn hashes_relevant_keys_only() {
+        // composer's hash of an empty composer.json: md5("[]")
+        assert_eq!(content_hash("{}").unwrap(), "d751713988987e9331980363e24189ce");
+
+        let manifest = r#"{
+            "name": "acme/app",
+            "description": "ignored",
+            "require": {"php": ">=8.1", "monolog/monolog": "^3.0"},
+            "autoload": {"psr-4": {"App\\": "src/"}},
+            "config": {"sort-packages": true, "platform": {"php": "8.1.0"}}
+        }"#;
+        let relevant = r#"{"config":{"platform":{"php":"8.1.0"}},"name":"acme\/app","require":{"php":">=8.1","monolog\/monolog":"^3.0"}}"#;
+        assert_eq!(content_hash(manifest).unwrap(), hex::encode(Md5::digest(relevant)));
+
+        // reordering keys or touching irrelevant ones keeps the hash, changing a requirement doesn't
+        let reordered = r#"{"require": {"php": ">=8.1", "monolog/monolog": "^3.0"}, "config": {"platform": {"php": "8.1.0"}}, "name": "acme/app"}"#;
+        assert_eq!(content_hash(reordered).unwrap(), content_hash(manifest).unwrap());
+        assert_ne!(content_hash(&manifest.replace("^3.0", "^3.1")).unwrap(), content_hash(manifest).unwrap());
+    }
+
+    // load and save a lock written by composer
+    fn round_trip(fixture: &str) -> (LockFile, String, String) {
+        let path = format!("tests/fixtures/lock/{}", fixture);
+        let lock = LockFile::load(&path).unwrap();
+
+        let out = std::env::temp_dir().join(format!("maestro-lock-{}-{}", std::process::id(), fixture));
+        lock.save(out.to_str().unwrap()).unwrap();
+        let saved = fs::read_to_string(&out).unwrap();
+        fs::remove_file(&out).unwrap();
+
+        (lock, saved, fs::read_to_string(&path).unwrap())
+    }
+
+    #[test]
+    fn round_trips_composer_lock() {
+        // byte for byte: same keys in the same order, same formatting
+        let (lock, saved, original) = round_trip("composer.lock");
+        assert_eq!(saved, original);
+
+        let manifest = fs::read_to_string("tests/fixtures/lock/composer.json").unwrap();
+        assert_eq!(lock.content_hash, content_hash(&manifest).unwrap());
+        let monolog = &lock.packages[1];
+        assert_eq!(monolog.version_normalized, "3.5.0.0");
+        assert_eq!(monolog.package_type.as_deref(), Some("library"));
+        assert_eq!(monolog.require_dev.len(), 17);
+        assert_eq!(monolog.license, ["MIT"]);
+        assert_eq!(monolog.autoload.as_ref().unwrap()["psr-4"]["Monolog\\"], "src/Monolog");
+        assert_eq!(monolog.source.as_ref().unwrap().reference.as_deref(), Some("c915e2634718dbc8a4a15c61b0e62e7a44e14448"));
+        assert!(lock.packages.iter().chain(&lock.packages_dev).all(|p| p.dist.as_ref().unwrap().sha256.is_none()));
+        assert_eq!(lock.packages_dev[0].version_normalized, "1.9999999.9999999.9999999-dev");
+        assert!(lock.packages_dev[0].default_branch);
+        assert_eq!(lock.packages_dev[1].bin, ["phpstan", "phpstan.phar"]);
+        assert_eq!(lock.aliases[0].alias, "1.11.1");
+        assert_eq!(lock.stability_flags["myclabs/deep-copy"], 20);
+        assert_eq!(lock.plugin_api_version.as_deref(), Some("2.6.0"));
+    }
+
+    #[test]
+    fn reads_older_composer_lock() {
+        let (lock, saved, original) = round_trip("composer-2.2.lock");
+        let (saved, mut original): (Value, Value) = (serde_json::from_str(&saved).unwrap(), serde_json::from_str(&original).unwrap());
+        let manifest = fs::read_to_string("tests/fixtures/lock/composer-2.2.json").unwrap();
+        assert_eq!(lock.content_hash, content_hash(&manifest).unwrap());
+        assert_eq!(lock.packages[0].version_normalized, "1.28.0.0");
+        assert!(lock.stability_flags.is_empty() && lock.platform.is_empty());
+
+        // only the empty maps that composer 2.2 wrote as [] come back as {}
+        for key in ["stability-flags", "platform", "platform-dev"] {
+            original[key] = serde_json::json!({});
+        }
+        assert_eq!(saved, original);
+    }
+
+    #[test]
+    fn keeps_checksums_of_unchanged_references() {
+        let package = |name: &str, reference: &str, sha256: Option<&str>| PackageVersion {
+            name: Some(name.into()),
+            dist: Some(DistInfo {
+                r#type: "zip".into(),
+                url: format!("https://api.github.com/repos/{}/zipball/{}", name, reference),
+                reference: Some(reference.into()),
+                shasum: Some(String::new()),
+                sha256: sha256.map(String::from),
+                mirrors: None,
+            }),
+            ..Default::default()
+        };
+        let locked = [package("a/a", "aaa", Some("1111")), package("b/b", "bbb", Some("2222"))];
+        let mut resolved = [package("a/a", "aaa", None), package("b/b", "ccc", None), package("c/c", "ddd", None)];
+        keep_checksums(&mut resolved, &locked);
+
+        let sha256: Vec<Option<&str>> = resolved.iter().map(|p| p.dist.as_ref().unwrap().sha256.as_deref()).collect();
+        assert_eq!(sha256, [Some("1111"), None, None]);
+    }
+
+    #[test]
+    fn writes_numeric_stability_flags() {
+        let manifest: ComposerManifest = serde_json::from_value(serde_json::json!({
+            "minimum-stability": "beta",
+            "prefer-stable": true,
+            "require": {"a/a": "1.0.*@beta", "b/b": "dev-main", "c/c": "1.0.0-RC1", "d/d": "2.0.0-alpha1", "e/e": "^1.0"},
+            "require-dev": {"f/f": "@alpha", "g/g": "@stable"},
+        })).unwrap();
+        let lock = LockFile::new(Vec::new(), Vec::new(), &manifest, String::new());
+
+        let flags: Vec<(&str, u8)> = lock.stability_flags.iter().map(|(name, flag)| (name.as_str(), *flag)).collect();
+        assert_eq!(flags, [("a/a", 10), ("b/b", 20), ("d/d", 15), ("f/f", 15), ("g/g", 0)]);
+        assert_eq!(lock.minimum_stability, "beta");
+        assert!(lock.prefer_stable);
+
+        let json = to_json(&lock).unwrap();
+        assert!(json.contains("\"stability-flags\": {\n        \"a/a\": 10,\n        \"b/b\": 20,\n"), "{}", json);
+    }
+}
@@ -5,12 +5,24 @@ mod installer;
 mod generator;
 mod cache;
 mod lock; // <--- Register module
-
-use manifest::ComposerManifest;
-use registry::{RegistryClient, PackageVersion};
-use semver_compat::to_rust_version;
+mod solver;
+mod version;
+mod platform;
+mod repository;
+mod auth;
+mod installed;
+
+use auth::Auth;
+use installed::Installed;
+use manifest::{ComposerManifest, PreferredInstall};
+use registry::{DistInfo, PackageVersion, SourceInfo};
+use repository::RepositorySet;
+use solver::{is_platform_package, Pool, Request, Solver, StabilityPolicy, UpdateReach};
+use platform::Platform;
+use semver_compat::Constraint;
+use version::{PhpVersion, Stability};
 use anyhow::{Context, Result};
-use std::collections::{VecDeque, HashSet};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fs;
 use std::path::Path; // Need Path to check existence
 use std::sync::Arc;
@@ -30,11 +42,58 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    Install,
-    Update,
+    Install {
+        /// Skip packages listed in require-dev
+        #[arg(long)]
+        no_dev: bool,
+        /// Clone packages from their git source instead of downloading dist archives
+        #[arg(long, conflicts_with = "prefer_dist")]
+        prefer_source: bool,
+        /// Install packages from dist archives, even where preferred-install says source
+        #[arg(long)]
+        prefer_dist: bool,
+        /// Fail instead of warning when composer.lock is out of date with composer.json
+        #[arg(long)]
+        strict_lock: bool,
+    },
+    Update {
+        /// Only update these packages (wildcards allowed, e.g. "symfony/*"); the rest stay locked
+        packages: Vec<String>,
+        /// Also update the dependencies of the listed packages, except those the root requires itself
+        #[arg(short = 'w', long, conflicts_with = "with_all_dependencies")]
+        with_dependencies: bool,
+        /// Also update all dependencies of the listed packages, root requirements included
+        #[arg(short = 'W', long)]
+        with_all_dependencies: bool,
+        /// Keep every package locked and only refresh composer.lock's content-hash
+        #[arg(long, conflicts_with = "packages")]
+        lock: bool,
+        /// Skip packages listed in require-dev (they are still locked)
+        #[arg(long)]
+        no_dev: bool,
+        /// Clone packages from their git source instead of downloading dist archives
+        #[arg(long, conflicts_with = "prefer_dist")]
+        prefer_source: bool,
+        /// Install packages from dist archives, even where preferred-install says source
+        #[arg(long)]
+        prefer_dist: bool,
+    },
     Add {
         name: String
     },
+    /// Remove a package from the requirements, along with the dependencies nothing else needs
+    Remove {
+        name: String,
+        /// Remove it from require-dev
+        #[arg(long)]
+        dev: bool,
+    },
+    /// Check that php and its extensions satisfy the requirements in composer.lock
+    CheckPlatformReqs {
+        /// Ignore the requirements of packages listed in packages-dev
+        #[arg(long)]
+        no_dev: bool,
+    },
 }
 
 
@@ -43,30 +102,75 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     // default: "install"
-    match cli.command.unwrap_or(Commands::Install) {
-        Commands::Install => run_install().await?,
-        Commands::Update => run_update().await?,
+    match cli.command.unwrap_or(Commands::Install { no_dev: false, prefer_source: false, prefer_dist: false, strict_lock: false }) {
+        Commands::Install { no_dev, prefer_source, prefer_dist, strict_lock } => {
+            run_install(no_dev, preferred_install(prefer_source, prefer_dist), strict_lock).await?
+        }
+        Commands::Update { packages, with_dependencies, with_all_dependencies, lock, no_dev, prefer_source, prefer_dist } => {
+            let reach = if with_all_dependencies {
+                UpdateReach::AllDependencies
+            } else if with_dependencies {
+                UpdateReach::Dependencies
+            } else {
+                UpdateReach::Listed
+            };
+            // --lock is a partial update of nothing
+            let partial = (lock || !packages.is_empty()).then_some(PartialUpdate { packages, reach });
+            run_update(no_dev, preferred_install(prefer_source, prefer_dist), partial).await?
+        }
         Commands::Add { name } => run_add(&name).await?,
+        Commands::Remove { name, dev } => run_remove(&name, dev).await?,
+        Commands::CheckPlatformReqs { no_dev } => run_check_platform_reqs(no_dev)?,
     }
 
     Ok(())
 }
 
-async fn run_install() -> Result<()> {
+// `update <packages>`: what may change, everything else keeps its locked version
+struct PartialUpdate {
+    packages: Vec<String>,
+    reach: UpdateReach,
+}
+
+// --prefer-source/--prefer-dist override config.preferred-install
+fn preferred_install(prefer_source: bool, prefer_dist: bool) -> Option<PreferredInstall> {
+    if prefer_source {
+        Some(PreferredInstall::Source)
+    } else if prefer_dist {
+        Some(PreferredInstall::Dist)
+    } else {
+        None
+    }
+}
+
+async fn run_install(no_dev: bool, prefer: Option<PreferredInstall>, strict_lock: bool) -> Result<()> {
     let lock_path = "composer.lock";
     if Path::new(lock_path).exists() {
         println!("{}", "Lockfile found. Installing locked dependencies...".bold().cyan());
         let lockfile = lock::LockFile::load(lock_path).context("Failed to read lockfile")?;
-        download_and_install(lockfile.packages).await?;
+        // the root package's config and metadata; the lock alone is enough to install
+        let content = fs::read_to_string("composer.json").ok();
+        let manifest = content.as_deref()
+            .and_then(|content| serde_json::from_str::<ComposerManifest>(content).ok())
+            .unwrap_or_default();
+        if let Some(content) = &content
+            && lockfile.content_hash != lock::content_hash(content)?
+        {
+            if strict_lock {
+                anyhow::bail!("The lock file is not up to date with the latest changes in composer.json. Run `maestro update` to update it.");
+            }
+            eprintln!("{} The lock file is not up to date with the latest changes in composer.json. You may be getting outdated dependencies. It is recommended that you run `maestro update` or `maestro update <package name>`.", "Warning:".yellow().bold());
+        }
+        download_and_install(lockfile.packages, lockfile.packages_dev, !no_dev, Arc::new(Auth::load()?), prefer, &manifest).await?;
     } else {
         println!("{}", "No lockfile found. Running resolution...".bold().cyan());
-        run_update().await?;
+        run_update(no_dev, prefer, None).await?;
     }
 
     Ok(())
 }
 
-async fn run_update() -> Result<()> {
+async fn run_update(no_dev: bool, prefer: Option<PreferredInstall>, partial: Option<PartialUpdate>) -> Result<()> {
     println!("{}", "Updating dependencies...".bold().cyan());
 
     let path = "composer.json";
@@ -74,87 +178,306 @@ async fn run_update() -> Result<()> {
 
     let content = fs::read_to_string(path).context("Read composer.json failed")?;
     let manifest: ComposerManifest = serde_json::from_str(&content)?;
-    let client = Arc::new(RegistryClient::new());
-
-    let mut queue: VecDeque<(String, String)> = VecDeque::new();
-    let mut installed_set: HashSet<String> = HashSet::new();
-    let mut resolved_packages: Vec<PackageVersion> = Vec::new();
+    let auth = Arc::new(Auth::load()?);
+    let repositories = Arc::new(RepositorySet::from_manifest(&manifest, &auth).await?);
 
     let start_time = std::time::Instant::now();
 
-    for (name, constraint) in manifest.require {
-        queue.push_back((name, constraint));
+    let request = Request::from_manifest(&manifest);
+
+    let policy = StabilityPolicy {
+        minimum: manifest.minimum_stability(),
+        flags: manifest.stability_flags(),
+        prefer_stable: manifest.prefer_stable(),
+    };
+    let platform = Platform::detect().with_overrides(&manifest.config.platform);
+    if !platform.is_detected() {
+        eprintln!("{} php could not be run, php and ext-* requirements are not checked", "Warning:".yellow().bold());
     }
+    let pinned = match &partial {
+        Some(partial) => pinned_packages(lock_path, partial, &request)?,
+        None => Vec::new(),
+    };
+    let pool = load_pool(&repositories, &request.roots(), policy, platform, pinned).await;
+    let resolved_packages = Solver::new(&pool, &request)?.solve()?;
+    let (mut packages, mut packages_dev) = solver::partition_dev(resolved_packages, &request.require);
+    if let Ok(previous) = lock::LockFile::load(lock_path) {
+        let locked: Vec<PackageVersion> = previous.packages.into_iter().chain(previous.packages_dev).collect();
+        lock::keep_checksums(&mut packages, &locked);
+        lock::keep_checksums(&mut packages_dev, &locked);
+    }
+    record_checksums(&mut packages, &auth).await;
+    record_checksums(&mut packages_dev, &auth).await;
 
-    // Parallel resolution: fetch metadata for multiple packages at once
-    while !queue.is_empty() {
-        // Collect all packages that need resolution in this batch
-        let mut batch = Vec::new();
-        while let Some((pkg_name, version_constraint)) = queue.pop_front() {
-            if installed_set.contains(&pkg_name) { continue; }
-            batch.push((pkg_name, version_constraint));
+    println!("{}", format!("Resolution complete in {:.2?}", start_time.elapsed()).bold());
+
+    let lock_data = lock::LockFile::new(packages.clone(), packages_dev.clone(), &manifest, lock::content_hash(&content)?);
+    lock_data.save(lock_path)?;
+    println!("{}", "Generated composer.lock".green());
+
+    download_and_install(packages, packages_dev, !no_dev, auth, prefer, &manifest).await?;
+    
+    Ok(())
+}
+
+// The locked packages a partial update must not touch. Without a lock file nothing is locked.
+fn pinned_packages(lock_path: &str, partial: &PartialUpdate, request: &Request) -> Result<Vec<PackageVersion>> {
+    if !Path::new(lock_path).exists() {
+        return Ok(Vec::new());
+    }
+    let lockfile = lock::LockFile::load(lock_path).context("Failed to read lockfile")?;
+    let locked: Vec<PackageVersion> = lockfile.packages.into_iter().chain(lockfile.packages_dev).collect();
+
+    let roots = request.roots();
+    for pattern in &partial.packages {
+        let known = locked.iter().filter_map(|p| p.name.as_deref())
+            .chain(roots.iter().map(|(name, _)| name.as_str()))
+            .any(|name| repository::matches_name(pattern, name));
+        if !known {
+            eprintln!("{} Package \"{}\" listed for update is not locked.", "Warning:".yellow().bold(), pattern);
         }
+    }
 
-        if batch.is_empty() { break; }
+    let allowed = solver::allowed_updates(&locked, &partial.packages, &roots, partial.reach);
+    Ok(locked.into_iter()
+        .filter(|p| p.name.as_deref().is_some_and(|name| !allowed.contains(name)))
+        .collect())
+}
 
-        // Fetch metadata for all packages in parallel
+// Fetch metadata for every package that could take part in resolution.
+// Packages are fetched in parallel batches; each version matching a constraint seen so far
+// contributes its own requirements to the next batch.
+// Pinned packages are taken from the lock as they are, without fetching anything for them.
+async fn load_pool(repositories: &Arc<RepositorySet>, root: &[(String, String)], policy: StabilityPolicy, platform: Platform, pinned: Vec<PackageVersion>) -> Pool {
+    let mut pool = Pool::new(policy.clone(), platform);
+    for package in pinned {
+        pool.pin(package);
+    }
+    let mut queue: VecDeque<(String, String)> = root.iter().cloned().collect();
+    let mut scanned: HashSet<(String, String)> = HashSet::new();
+    let mut fetched: HashSet<String> = HashSet::new();
+    // branches come from a separate file, only loaded once something may need them
+    let mut fetched_dev: HashSet<String> = HashSet::new();
+
+    while !queue.is_empty() {
+        let batch: Vec<(String, String)> = queue.drain(..)
+            .filter(|link| scanned.insert(link.clone()))
+            .collect();
+
+        // Fetch metadata for all new packages in parallel
         let mut fetch_set = JoinSet::new();
-        for (pkg_name, version_constraint) in batch {
-            let client = Arc::clone(&client);
-            fetch_set.spawn(async move {
-                let result = resolve_package(&*client, &pkg_name, &version_constraint).await;
-                (pkg_name, version_constraint, result)
-            });
+        for (pkg_name, version_constraint) in &batch {
+            if is_platform_package(pkg_name) || pool.is_pinned(pkg_name) { continue; }
+            if fetched.insert(pkg_name.clone()) {
+                let repositories = Arc::clone(repositories);
+                let pkg_name = pkg_name.clone();
+                fetch_set.spawn(async move {
+                    let result = repositories.find(&pkg_name, false).await;
+                    (pkg_name, result)
+                });
+            }
+            if policy.wants_dev(pkg_name, version_constraint) && fetched_dev.insert(pkg_name.clone()) {
+                let repositories = Arc::clone(repositories);
+                let pkg_name = pkg_name.clone();
+                fetch_set.spawn(async move {
+                    let result = repositories.find(&pkg_name, true).await;
+                    (pkg_name, result)
+                });
+            }
         }
 
-        // Process results
         while let Some(res) = fetch_set.join_next().await {
             match res {
-                Ok((pkg_name, _version_constraint, Ok(Some(best_package)))) => {
-                    // Add dependencies to queue
-                    for (dep_name, dep_constraint) in &best_package.require {
-                        if dep_name == "php" || dep_name.starts_with("ext-") { continue; }
-                        if !installed_set.contains(dep_name) {
-                            queue.push_back((dep_name.clone(), dep_constraint.clone()));
-                        }
-                    }
-
-                    installed_set.insert(pkg_name.clone());
-                    resolved_packages.push(best_package);
-                }
-                Ok((pkg_name, version_constraint, Ok(None))) => {
-                    eprintln!("{} Could not resolve {} {}", "Warning:".yellow().bold(), pkg_name, version_constraint);
-                }
-                Ok((pkg_name, _, Err(e))) => {
-                    eprintln!("{} Error resolving {}: {}", "Warning:".yellow().bold(), pkg_name, e);
+                Ok((pkg_name, Ok(versions))) => pool.insert(&pkg_name, versions),
+                Ok((pkg_name, Err(e))) => {
+                    eprintln!("{} Error fetching {}: {}", "Warning:".yellow().bold(), pkg_name, e);
                 }
                 Err(e) => {
                     eprintln!("{} Task panic: {}", "Warning:".yellow().bold(), e);
                 }
             }
         }
+
+        // Queue the requirements of every version the solver might pick
+        for (pkg_name, version_constraint) in &batch {
+            let Ok(constraint) = Constraint::parse(version_constraint) else { continue; };
+            for candidate in pool.matching(pkg_name, &constraint) {
+                for dep in &candidate.requires {
+                    let link = (dep.target.clone(), dep.pretty_constraint.clone());
+                    if !scanned.contains(&link) {
+                        queue.push_back(link);
+                    }
+                }
+            }
+        }
     }
 
-    println!("{}", format!("Resolution complete in {:.2?}", start_time.elapsed()).bold());
+    pool
+}
 
-    let lock_data = lock::LockFile::new(resolved_packages.clone());
-    lock_data.save(lock_path)?;
-    println!("{}", "Generated composer.lock".green());
-    
-    download_and_install(resolved_packages).await?;
-    
+// Compare the local php and extensions (ignoring config.platform) with every platform
+// requirement of the locked packages and the root package.
+fn run_check_platform_reqs(no_dev: bool) -> Result<()> {
+    let lockfile = lock::LockFile::load("composer.lock").context("No composer.lock found, run update first")?;
+    let platform = Platform::detect();
+    if !platform.is_detected() {
+        anyhow::bail!("php could not be run, so the platform requirements cannot be checked.");
+    }
+
+    println!("{}", "Checking platform requirements for packages in composer.lock".bold().cyan());
+
+    // platform package -> (required by, constraint)
+    let mut requirements: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+    let mut root = lockfile.platform.clone();
+    let mut packages = lockfile.packages;
+    if !no_dev {
+        root.extend(lockfile.platform_dev.clone());
+        packages.extend(lockfile.packages_dev);
+    }
+    for (name, constraint) in root {
+        requirements.entry(name).or_default().push(("composer.json".to_string(), constraint));
+    }
+    for pkg in &packages {
+        let pkg_name = pkg.name.clone().unwrap_or_default();
+        for (name, constraint) in pkg.require.iter().filter(|(name, _)| is_platform_package(name)) {
+            requirements.entry(name.clone()).or_default().push((pkg_name.clone(), constraint.clone()));
+        }
+    }
+
+    // (name, version, reason, status)
+    let mut rows: Vec<(String, String, String, &str)> = Vec::new();
+    for (name, required_by) in &requirements {
+        if !platform.checks(name) {
+            continue;
+        }
+        let reason = |(pkg, constraint): &(String, String)| format!("{} requires {} ({})", pkg, name, constraint);
+
+        let Some(version) = platform.version(name) else {
+            rows.push((name.clone(), "n/a".into(), reason(&required_by[0]), "missing"));
+            continue;
+        };
+        let parsed = PhpVersion::parse(version)?;
+        let failures: Vec<&(String, String)> = required_by.iter()
+            .filter(|(_, constraint)| !Constraint::parse(constraint).is_ok_and(|c| c.matches(&parsed)))
+            .collect();
+        if failures.is_empty() {
+            rows.push((name.clone(), version.into(), String::new(), "success"));
+        }
+        for failure in failures {
+            rows.push((name.clone(), version.into(), reason(failure), "failed"));
+        }
+    }
+
+    let name_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+    let version_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0);
+    let reason_width = rows.iter().map(|r| r.2.len()).max().unwrap_or(0);
+    for (name, version, reason, status) in &rows {
+        let status = match *status {
+            "success" => status.green(),
+            _ => status.red(),
+        };
+        println!("{:name_width$}  {:version_width$}  {:reason_width$}  {}", name, version, reason, status);
+    }
+
+    if rows.iter().any(|r| r.3 != "success") {
+        anyhow::bail!("Your platform does not satisfy the requirements of composer.lock.");
+    }
     Ok(())
 }
 
-async fn download_and_install(packages: Vec<PackageVersion>) -> Result<()> {
+// Download every locked archive so composer.lock can pin its sha256; install checks against it.
+async fn record_checksums(packages: &mut [PackageVersion], auth: &Arc<Auth>) {
+    let mut set = JoinSet::new();
+    for (i, pkg) in packages.iter().enumerate() {
+        let Some(dist) = pkg.dist.clone() else { continue };
+        // kept from the lock: the checksum of the first download stays
+        if dist.r#type == "path" || dist.sha256.is_some() {
+            continue;
+        }
+        let name = pkg.name.clone().unwrap_or_default();
+        let auth = auth.clone();
+        set.spawn(async move {
+            let bytes = installer::download(&name, &dist, &auth).await;
+            (i, bytes.map(|bytes| installer::sha256(&bytes)))
+        });
+    }
+
+    while let Some(res) = set.join_next().await {
+        let Ok((i, hash)) = res else { continue };
+        match hash {
+            Ok(hash) => {
+                if let Some(dist) = &mut packages[i].dist {
+                    dist.sha256 = Some(hash);
+                }
+            }
+            Err(e) => {
+                let name = packages[i].name.as_deref().unwrap_or("unknown");
+                eprintln!("{} {}: {:#}", "Warning:".yellow().bold(), name, e);
+            }
+        }
+    }
+}
+
+// where download_and_install gets a package from
+enum Download {
+    Dist(DistInfo, Option<bool>),
+    Source(SourceInfo),
+}
+
+// Bring vendor/ in line with the locked packages: install and update them, remove what is no longer
+// locked, and record the result in vendor/composer/installed.json and installed.php.
+async fn download_and_install(packages: Vec<PackageVersion>, packages_dev: Vec<PackageVersion>, dev: bool, auth: Arc<Auth>, prefer: Option<PreferredInstall>, root: &ComposerManifest) -> Result<()> {
+    let vendor = Path::new("vendor");
+    let (packages, dev_package_names) = installed::select(packages, packages_dev, dev);
+
+    let mut previous = Installed::load(vendor);
+    // whatever was deleted from vendor/ by hand has to be installed again
+    previous.packages.retain(|entry| {
+        let has_files = entry.package.dist.is_some() || entry.package.source.is_some();
+        let name = entry.package.name.as_deref().unwrap_or_default();
+        !has_files || fs::symlink_metadata(vendor.join(name)).is_ok()
+    });
+    let operations = installed::operations(&previous, &packages);
+    installed::print_summary(&operations);
+    let removals: Vec<String> = operations.iter()
+        .filter(|op| matches!(op, installed::Operation::Uninstall(_)))
+        .map(|op| op.name().to_string())
+        .collect();
+    // packages already installed in the locked version and reference are left alone
+    let changed: HashSet<String> = operations.iter()
+        .filter(|op| !matches!(op, installed::Operation::Uninstall(_)))
+        .map(|op| op.name().to_string())
+        .collect();
+
     let mut download_list = Vec::new();
     for pkg in &packages {
-        if let Some(dist) = &pkg.dist {
-            let name = pkg.name.clone().unwrap_or_else(|| "unknown".to_string());
-            download_list.push((name, pkg.version.clone(), dist.url.clone()));
+        let name = pkg.name.clone().unwrap_or_else(|| "unknown".to_string());
+        if !changed.contains(&name) {
+            continue;
         }
+        let from_source = match prefer.unwrap_or_else(|| root.config.preferred_install(&name)) {
+            PreferredInstall::Source => true,
+            PreferredInstall::Dist => false,
+            PreferredInstall::Auto => PhpVersion::from_normalized(&pkg.version_normalized).stability() == Stability::Dev,
+        };
+        // either way, fall back to whichever one the package has
+        let download = match (&pkg.dist, &pkg.source) {
+            (Some(_), Some(source)) if from_source => Download::Source(source.clone()),
+            (Some(dist), _) => {
+                let symlink = pkg.transport_options.as_ref().and_then(|o| o.get("symlink")).and_then(|v| v.as_bool());
+                Download::Dist(dist.clone(), symlink)
+            }
+            (None, Some(source)) => Download::Source(source.clone()),
+            (None, None) => {
+                eprintln!("{} {} has neither a dist nor a source, skipping", "Warning:".yellow().bold(), name);
+                continue;
+            }
+        };
+        download_list.push((name, pkg.version.clone(), download));
+    }
+    if !download_list.is_empty() {
+        println!("{}", format!("Downloading {} packages...", download_list.len()).cyan());
     }
-    println!("{}", format!("Downloading {} packages...", download_list.len()).cyan());
 
     // set up progress bar
     let pb = ProgressBar::new(download_list.len() as u64);
@@ -162,75 +485,83 @@ async fn download_and_install(packages: Vec<PackageVersion>) -> Result<()> {
         "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} packages ({eta})"
     ).unwrap().progress_chars("#>-"));
 
+    let staging = installer::Staging::begin(Path::new("vendor"))?;
+    let installed: Vec<String> = download_list.iter().map(|(name, _, _)| name.clone()).collect();
+
     let mut set = JoinSet::new();
-    for (name, version, url) in download_list {
+    for (name, version, download) in download_list {
+        let auth = auth.clone();
+        let staging = staging.clone();
         set.spawn(async move {
-            installer::install_package(&name, &version, &url).await
+            match download {
+                Download::Dist(dist, symlink) => installer::install_package(&name, &dist, symlink, &auth, &staging).await,
+                Download::Source(source) => installer::install_source(&name, &version, &source, &staging).await,
+            }
         });
     }
 
-    let mut success_count = 0;
-    while let Some(res) = set.join_next().await {
-        match res {
-            Ok(Ok(_)) => {
-                success_count += 1;
-                pb.inc(1);
-            },
-            Ok(Err(e)) => {
-                pb.println(format!("{} {}", "Failed:".red(), e));
-            },
-            Err(e) => {
-                pb.println(format!("{} {}", "Panic:".red(), e));
+    // with the handler in place Ctrl-C no longer kills the process, so the moves into vendor/ below
+    // can't be cut short either
+    let mut failed = 0;
+    loop {
+        tokio::select! {
+            res = set.join_next() => match res {
+                Some(Ok(Ok(_))) => pb.inc(1),
+                Some(Ok(Err(e))) => {
+                    failed += 1;
+                    pb.println(format!("{} {}", "Failed:".red(), e));
+                },
+                Some(Err(e)) => {
+                    failed += 1;
+                    pb.println(format!("{} {}", "Panic:".red(), e));
+                },
+                None => break,
             },
+            _ = tokio::signal::ctrl_c() => {
+                set.abort_all();
+                pb.abandon();
+                staging.abort();
+                anyhow::bail!("Interrupted, vendor/ was left unchanged");
+            }
         }
     }
 
-    pb.finish_with_message(format!("Installed {} packages", success_count));
-
-    generator::generate_autoload("vendor")?;
+    if failed > 0 {
+        pb.abandon();
+        staging.abort();
+        anyhow::bail!("{} of {} packages could not be installed, vendor/ was left unchanged", failed, installed.len());
+    }
+    staging.commit(&installed, &removals)?;
+    pb.finish_with_message(format!("Installed {} packages", installed.len()));
+    let state = Installed::new(&packages, dev_package_names, dev);
+    state.save(vendor)?;
+    generator::generate_installed_versions("vendor", root, &state)?;
+
+    let names: Vec<String> = packages.iter().filter_map(|p| p.name.clone()).collect();
+    generator::generate_autoload("vendor", &names)?;
     println!("{} Autoload files generated.", "Success:".green().bold());
 
     Ok(())
 }
-async fn resolve_package(
-    client: &RegistryClient,
-    pkg_name: &str,
-    constraint: &str
-) -> Result<Option<PackageVersion>> {
-    let versions = client.get_package_metadata(pkg_name).await?;
-
-    let mut compatible_versions: Vec<(&PackageVersion, semver::Version)> = versions.iter()
-        .filter_map(|v| {
-            let rust_v = to_rust_version(&v.version_normalized)?;
-            if semver_compat::version_matches(constraint, &v.version_normalized) {
-                Some((v, rust_v))
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    if compatible_versions.is_empty() {
-        return Ok(None);
-    }
-
-    compatible_versions.sort_by(|a, b| a.1.cmp(&b.1));
-    Ok(compatible_versions.last().map(|(pkg, _)| (**pkg).clone()))
-}
-
 async fn run_add(pkg_name: &str) -> Result<()> {
     println!("{}", format!("Adding {}...", pkg_name).bold().cyan());
 
-    let client = RegistryClient::new();
-    let versions = client.get_package_metadata(pkg_name).await.context("Could not find package.")?;
+    let path = "composer.json";
+    let content = fs::read_to_string(path).context("Read composer.json failed")?;
+    let mut manifest: ComposerManifest = serde_json::from_str(&content)?;
+
+    let repositories = RepositorySet::from_manifest(&manifest, &Arc::new(Auth::load()?)).await?;
+    let versions = repositories.find(pkg_name, false).await?;
+    if versions.is_empty() {
+        anyhow::bail!("Could not find package {}.", pkg_name);
+    }
 
     // find latest STABLE version
     let latest = versions.iter()
-        .filter(|v| {
-            let s = &v.version_normalized;
-            !s.contains("dev") && !s.contains("alpha") && !s.contains("beta") && !s.contains("RC")
-        })
-        .max_by_key(|v| to_rust_version(&v.version_normalized));
+        .map(|v| (v, PhpVersion::from_normalized(&v.version_normalized)))
+        .filter(|(_, parsed)| parsed.stability() == Stability::Stable)
+        .max_by(|a, b| a.1.cmp(&b.1))
+        .map(|(v, _)| v);
 
     let target_version = match latest {
         Some(v) => {
@@ -249,10 +580,6 @@ async fn run_add(pkg_name: &str) -> Result<()> {
     println!("    Selected version: {}", target_version.green());
 
     // edit composer.json
-    let path = "composer.json";
-    let content = fs::read_to_string(path).context("Read composer.json failed")?;
-    let mut manifest: ComposerManifest = serde_json::from_str(&content)?;
-
     // insert new requirement
     manifest.require.insert(pkg_name.to_string(), target_version);
 
@@ -261,5 +588,36 @@ async fn run_add(pkg_name: &str) -> Result<()> {
 
     println!("{}", format!("Added {} to composer.json", pkg_name).green());
 
-    run_update().await
-}
\ No newline at end of file
+    run_update(false, None, None).await
+}
+
+async fn run_remove(pkg_name: &str, dev: bool) -> Result<()> {
+    println!("{}", format!("Removing {}...", pkg_name).bold().cyan());
+
+    let path = "composer.json";
+    let content = fs::read_to_string(path).context("Read composer.json failed")?;
+
+    let (section, other) = if dev { ("require-dev", "require") } else { ("require", "require-dev") };
+    let Some((edited, key)) = manifest::remove_link(&content, section, pkg_name)? else {
+        if manifest::remove_link(&content, other, pkg_name)?.is_some() {
+            anyhow::bail!("{} could not be found in {} but it is present in {}.", pkg_name, section, other);
+        }
+        anyhow::bail!("{} is not required in your composer.json and has not been removed.", pkg_name);
+    };
+    let lock_path = "composer.lock";
+    let lock_content = fs::read_to_string(lock_path).ok();
+    fs::write(path, edited)?;
+    println!("    Removed {} from {}", key.green(), section);
+
+    // everything still needed stays locked; what nothing requires anymore drops out
+    let result = run_update(false, None, Some(PartialUpdate { packages: Vec::new(), reach: UpdateReach::Listed })).await;
+    if result.is_err() {
+        eprintln!("{}", format!("Removal failed, reverting ./{} to its original content.", path).red());
+        fs::write(path, &content)?;
+        match &lock_content {
+            Some(lock_content) => fs::write(lock_path, lock_content)?,
+            None => { fs::remove_file(lock_path).ok(); }
+        }
+    }
+    result
+}
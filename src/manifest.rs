@@ -1,7 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use crate::repository::matches_name;
+use crate::semver_compat::{atoms, split_stability_flag};
+use crate::version::{parse_stability, Stability};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Default)]
 pub struct ComposerManifest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
@@ -12,24 +17,240 @@ pub struct ComposerManifest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub license: Option<String>,
 
-    // dependencies
+    // dependencies, in the order composer.json lists them
     #[serde(default)]
-    pub require: HashMap<String, String>,
+    pub require: IndexMap<String, String>,
 
     #[serde(default, rename = "require-dev")]
-    pub require_dev: HashMap<String, String>,
+    pub require_dev: IndexMap<String, String>,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub conflict: IndexMap<String, String>,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub replace: IndexMap<String, String>,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub provide: IndexMap<String, String>,
 
     #[serde(default)]
     pub autoload: AutoloadConfig,
 
+    #[serde(default, rename = "minimum-stability", skip_serializing_if = "Option::is_none")]
+    pub minimum_stability: Option<String>,
+
+    #[serde(default, rename = "prefer-stable", skip_serializing_if = "Option::is_none")]
+    pub prefer_stable: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Config::is_empty")]
+    pub config: Config,
+
+    // kept as written (a list, or an object keyed by label); interpreted by RepositorySet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repositories: Option<serde_json::Value>,
+
     // capture other fields as a generic value to not lose data
     #[serde(flatten)]
     pub extra: HashMap<String, serde_json::Value>,
 }
 
+impl ComposerManifest {
+    pub fn minimum_stability(&self) -> Stability {
+        self.minimum_stability.as_deref()
+            .and_then(Stability::parse)
+            .unwrap_or(Stability::Stable)
+    }
+
+    pub fn prefer_stable(&self) -> bool {
+        self.prefer_stable.unwrap_or(false)
+    }
+
+    // Per-package stability overrides, as composer's RootPackageLoader::extractStabilityFlags() computes them:
+    // an explicit "@beta" wins, otherwise a constraint naming an unstable version ("1.0.0-RC1", "dev-main")
+    // lowers the bar for that package if it is below minimum-stability.
+    pub fn stability_flags(&self) -> BTreeMap<String, Stability> {
+        let minimum = self.minimum_stability();
+        let mut flags: BTreeMap<String, Stability> = BTreeMap::new();
+
+        for (name, constraint) in self.require.iter().chain(self.require_dev.iter()) {
+            let name = name.to_lowercase();
+            let atoms = atoms(constraint);
+
+            let explicit: Vec<Stability> = atoms.iter()
+                .filter_map(|atom| split_stability_flag(atom).ok()?.1)
+                .collect();
+            if !explicit.is_empty() {
+                for stability in explicit {
+                    if flags.get(&name).is_none_or(|existing| *existing >= stability) {
+                        flags.insert(name.clone(), stability);
+                    }
+                }
+                continue;
+            }
+
+            for atom in &atoms {
+                let stability = parse_stability(atom.trim_start_matches(['=', '>', '<', '!', '^', '~']));
+                if stability == Stability::Stable || minimum < stability {
+                    continue;
+                }
+                if flags.get(&name).is_none_or(|existing| *existing >= stability) {
+                    flags.insert(name.clone(), stability);
+                }
+            }
+        }
+
+        flags
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct Config {
+    // fake platform packages: "php": "8.1.0" pretends that version is installed, "ext-foo": false hides it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub platform: BTreeMap<String, serde_json::Value>,
+
+    // "dist", "source", "auto", or per package: {"my-vendor/*": "source", "*": "dist"}
+    #[serde(default, rename = "preferred-install", skip_serializing_if = "Option::is_none")]
+    pub preferred_install: Option<serde_json::Value>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl Config {
+    fn is_empty(&self) -> bool {
+        self.platform.is_empty() && self.preferred_install.is_none() && self.extra.is_empty()
+    }
+
+    // the first matching pattern wins, as in composer
+    pub fn preferred_install(&self, name: &str) -> PreferredInstall {
+        let preference = match &self.preferred_install {
+            Some(serde_json::Value::String(preference)) => Some(preference.as_str()),
+            Some(serde_json::Value::Object(patterns)) => patterns.iter()
+                .find(|(pattern, _)| matches_name(pattern, name))
+                .and_then(|(_, preference)| preference.as_str()),
+            _ => None,
+        };
+        match preference {
+            Some("source") => PreferredInstall::Source,
+            Some("auto") => PreferredInstall::Auto,
+            _ => PreferredInstall::Dist,
+        }
+    }
+}
+
+// where packages are installed from: the dist archive, a git clone of the source, or
+// (auto) a clone for dev versions only
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreferredInstall {
+    Dist,
+    Source,
+    Auto,
+}
+
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct AutoloadConfig {
     #[serde(default, rename = "psr-4")]
-    pub psr4: HashMap<String, String>, // "Monolog\\" => "src/"
+    pub psr4: BTreeMap<String, String>, // "Monolog\\" => "src/"
     // @todo: add psr-0 classmap
-}
\ No newline at end of file
+}
+// Drop `name` (in any case) from one section ("require" or "require-dev") of composer.json. The file
+// is edited as plain JSON, so every other key keeps its place and value; it is written back the way
+// composer writes it: 4-space indent, "/" and unicode unescaped. None when the section doesn't have it,
+// otherwise the new content and the key as it was written.
+pub fn remove_link(content: &str, section: &str, name: &str) -> Result<Option<(String, String)>> {
+    let mut json: serde_json::Value = serde_json::from_str(content).context("composer.json is not valid JSON")?;
+    let Some(links) = json.get_mut(section).and_then(|links| links.as_object_mut()) else { return Ok(None); };
+    let Some(key) = links.keys().find(|key| key.eq_ignore_ascii_case(name)).cloned() else { return Ok(None); };
+    links.shift_remove(&key);
+    Ok(Some((crate::lock::to_json(&json)?, key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_stability_flags_like_composer() {
+        // (minimum-stability, constraint, flag recorded for the package)
+        let cases = [
+            ("stable", "1.0.*@beta", Some(Stability::Beta)),
+            ("stable", "@dev", Some(Stability::Dev)),
+            ("stable", "^1.0@RC || ^2.0@alpha", Some(Stability::Alpha)),
+            ("dev", "^1.0@beta", Some(Stability::Beta)),
+            ("stable", "1.0.0-RC1", Some(Stability::RC)),
+            ("stable", "2.0.0-alpha3", Some(Stability::Alpha)),
+            ("stable", "dev-main", Some(Stability::Dev)),
+            ("stable", "1.x-dev", Some(Stability::Dev)),
+            ("beta", "2.0.0-alpha3", Some(Stability::Alpha)),
+            ("beta", "1.0.0-RC1", None),
+            ("dev", "dev-main", Some(Stability::Dev)),
+            ("stable", "^1.0", None),
+            ("stable", ">=1.0 <2.0", None),
+        ];
+        for (minimum, constraint, expected) in cases {
+            let manifest: ComposerManifest = serde_json::from_value(serde_json::json!({
+                "minimum-stability": minimum,
+                "require": {"Acme/Lib": constraint},
+            })).unwrap();
+            let flags = manifest.stability_flags();
+            assert_eq!(flags.get("acme/lib").copied(), expected, "{} with minimum-stability {}", constraint, minimum);
+        }
+    }
+
+    #[test]
+    fn keeps_the_least_stable_flag_across_require_and_require_dev() {
+        let manifest: ComposerManifest = serde_json::from_value(serde_json::json!({
+            "require": {"acme/lib": "^1.0@RC"},
+            "require-dev": {"acme/lib": "dev-main", "acme/tool": "@beta"},
+        })).unwrap();
+        let flags: Vec<(String, Stability)> = manifest.stability_flags().into_iter().collect();
+        assert_eq!(flags, [("acme/lib".to_string(), Stability::Dev), ("acme/tool".to_string(), Stability::Beta)]);
+    }
+
+    #[test]
+    fn removes_a_link_keeping_the_rest_of_composer_json() {
+        let content = concat!(
+            "{\n",
+            "  \"name\": \"acme/app\",\n",
+            "  \"description\": \"Déjà vu, see https://example.com/docs\",\n",
+            "  \"require-dev\": {\"phpunit/phpunit\": \"^10.0\"},\n",
+            "  \"require\": {\n",
+            "    \"php\": \">=8.1\",\n",
+            "    \"Monolog/Monolog\": \"^3.0\",\n",
+            "    \"psr/log\": \"^3.0\"\n",
+            "  },\n",
+            "  \"scripts\": {\"test\": \"vendor/bin/phpunit\"},\n",
+            "  \"autoload\": {\"psr-4\": {\"App\\\\\": \"src/\"}}\n",
+            "}\n",
+        );
+
+        let (edited, key) = remove_link(content, "require", "monolog/monolog").unwrap().unwrap();
+        assert_eq!(key, "Monolog/Monolog");
+        assert_eq!(edited, concat!(
+            "{\n",
+            "    \"name\": \"acme/app\",\n",
+            "    \"description\": \"Déjà vu, see https://example.com/docs\",\n",
+            "    \"require-dev\": {\n",
+            "        \"phpunit/phpunit\": \"^10.0\"\n",
+            "    },\n",
+            "    \"require\": {\n",
+            "        \"php\": \">=8.1\",\n",
+            "        \"psr/log\": \"^3.0\"\n",
+            "    },\n",
+            "    \"scripts\": {\n",
+            "        \"test\": \"vendor/bin/phpunit\"\n",
+            "    },\n",
+            "    \"autoload\": {\n",
+            "        \"psr-4\": {\n",
+            "            \"App\\\\\": \"src/\"\n",
+            "        }\n",
+            "    }\n",
+            "}\n",
+        ));
+
+        assert!(remove_link(content, "require-dev", "psr/log").unwrap().is_none());
+        assert!(remove_link(r#"{"name": "acme/app"}"#, "require", "psr/log").unwrap().is_none());
+        assert!(remove_link("not json", "require", "psr/log").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::Command;
+use crate::registry::PackageVersion;
+use crate::solver::is_platform_package;
+use crate::version::PhpVersion;
+
+// prints "name<TAB>version" per line for php itself and every loaded extension;
+// extension names may contain spaces ("Zend OPcache"), so a space can't separate the two
+const DETECT_SCRIPT: &str = r#"
+printf("php\t%d.%d.%d\n", PHP_MAJOR_VERSION, PHP_MINOR_VERSION, PHP_RELEASE_VERSION);
+if (PHP_INT_SIZE === 8) { printf("php-64bit\t%d.%d.%d\n", PHP_MAJOR_VERSION, PHP_MINOR_VERSION, PHP_RELEASE_VERSION); }
+if (PHP_ZTS) { printf("php-zts\t%d.%d.%d\n", PHP_MAJOR_VERSION, PHP_MINOR_VERSION, PHP_RELEASE_VERSION); }
+if (PHP_DEBUG) { printf("php-debug\t%d.%d.%d\n", PHP_MAJOR_VERSION, PHP_MINOR_VERSION, PHP_RELEASE_VERSION); }
+foreach (get_loaded_extensions() as $ext) { echo "ext-", $ext, "\t", phpversion($ext), "\n"; }
+"#;
+
+// The packages provided by the environment instead of the registry: the local php binary and
+// its extensions, optionally overridden by config.platform in composer.json.
+#[derive(Debug, Clone, Default)]
+pub struct Platform {
+    // name -> pretty version
+    pub packages: BTreeMap<String, String>,
+    // whether a php binary could be run; without one only overridden names are checked
+    detected: bool,
+    // names set (or hidden with `false`) by config.platform
+    overridden: BTreeSet<String>,
+}
+
+impl Platform {
+    pub fn detect() -> Self {
+        let Ok(output) = Command::new("php").arg("-r").arg(DETECT_SCRIPT).output() else {
+            return Self::default();
+        };
+        if !output.status.success() {
+            return Self::default();
+        }
+
+        let packages = parse_detected(&String::from_utf8_lossy(&output.stdout));
+        Self { packages, detected: true, overridden: BTreeSet::new() }
+    }
+
+    // apply config.platform: a version string fakes a package, `false` hides it
+    pub fn with_overrides(mut self, overrides: &BTreeMap<String, serde_json::Value>) -> Self {
+        for (name, value) in overrides {
+            let name = name.to_lowercase();
+            match value {
+                serde_json::Value::String(version) => {
+                    self.packages.insert(name.clone(), version.clone());
+                }
+                _ => {
+                    self.packages.remove(&name);
+                }
+            }
+            self.overridden.insert(name);
+        }
+        self
+    }
+
+    pub fn is_detected(&self) -> bool {
+        self.detected
+    }
+
+    // whether requirements on `name` are resolved against this platform;
+    // anything else (lib-*, composer-plugin-api, or everything when php is missing) is not checked
+    pub fn checks(&self, name: &str) -> bool {
+        self.overridden.contains(name)
+            || (self.detected && (name == "php" || name.starts_with("php-") || name.starts_with("ext-")))
+    }
+
+    pub fn version(&self, name: &str) -> Option<&str> {
+        self.packages.get(name).map(|v| v.as_str())
+    }
+
+    // the platform as installable packages, for the solver's pool
+    pub fn to_packages(&self) -> Vec<(String, PackageVersion)> {
+        self.packages.iter()
+            .filter(|(name, _)| is_platform_package(name))
+            .filter_map(|(name, version)| {
+                let parsed = PhpVersion::parse(version).ok()?;
+                Some((name.clone(), PackageVersion {
+                    name: Some(name.clone()),
+                    version: version.clone(),
+                    version_normalized: parsed.normalized().to_string(),
+                    ..Default::default()
+                }))
+            })
+            .collect()
+    }
+}
+
+// read DETECT_SCRIPT's output into name -> pretty version
+fn parse_detected(output: &str) -> BTreeMap<String, String> {
+    let mut packages = BTreeMap::new();
+    for line in output.lines() {
+        let (name, version) = line.split_once('\t').unwrap_or((line, ""));
+        // "Zend OPcache" -> ext-zend-opcache, like composer's PlatformRepository
+        let name = name.trim().to_lowercase().replace(' ', "-");
+        // extensions report all sorts of versions ("8.2.1-1ubuntu", "", "2.2.0 stable"); like composer, keep
+        // the leading x.y.z(.w) of one that doesn't parse, or fall back to 0
+        let version = version.split_whitespace().next().unwrap_or("");
+        let version = match PhpVersion::parse(version) {
+            Ok(_) => version.to_string(),
+            Err(_) => numeric_prefix(version).unwrap_or("0").to_string(),
+        };
+        packages.insert(name, version);
+    }
+    packages
+}
+
+// "8.2.1-1ubuntu" -> "8.2.1": three or four dot-separated numbers at the start
+fn numeric_prefix(version: &str) -> Option<&str> {
+    let mut end = 0;
+    let mut parts = 0;
+    for part in version.split('.').take(4) {
+        let digits = part.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            break;
+        }
+        end += digits + usize::from(parts > 0);
+        parts += 1;
+        if digits < part.len() {
+            break;
+        }
+    }
+    (parts >= 3).then(|| &version[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_detected_packages() {
+        let output = "php\t8.2.1\nphp-64bit\t8.2.1\next-Zend OPcache\t8.2.1\next-mbstring\t8.2.1-1ubuntu\next-apcu\t5.1.22 stable\next-date\t\next-pcov\t1.0.11.5-dev\next-ast\t1.1.1.1.1\n";
+        let packages = parse_detected(output);
+        let packages: Vec<(&str, &str)> = packages.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+        assert_eq!(packages, [
+            ("ext-apcu", "5.1.22"),
+            ("ext-ast", "1.1.1.1"),
+            ("ext-date", "0"),
+            ("ext-mbstring", "8.2.1"),
+            ("ext-pcov", "1.0.11.5-dev"),
+            ("ext-zend-opcache", "8.2.1"),
+            ("php", "8.2.1"),
+            ("php-64bit", "8.2.1"),
+        ]);
+    }
+
+    #[test]
+    fn applies_config_platform() {
+        let detected = Platform {
+            packages: parse_detected("php\t8.2.1\next-intl\t8.2.1\n"),
+            detected: true,
+            overridden: BTreeSet::new(),
+        };
+        let overrides = BTreeMap::from([
+            ("PHP".to_string(), serde_json::json!("8.1.99")),
+            ("ext-intl".to_string(), serde_json::json!(false)),
+            ("ext-gmp".to_string(), serde_json::json!("1.0.0")),
+        ]);
+        let platform = detected.with_overrides(&overrides);
+
+        assert_eq!(platform.version("php"), Some("8.1.99"));
+        assert_eq!(platform.version("ext-intl"), None);
+        assert_eq!(platform.version("ext-gmp"), Some("1.0.0"));
+        assert!(platform.checks("ext-intl") && platform.checks("ext-gmp") && platform.checks("ext-soap"));
+
+        // without php, only what config.platform names is checked
+        let platform = Platform::default().with_overrides(&overrides);
+        assert!(platform.checks("php") && platform.checks("ext-intl"));
+        assert!(!platform.checks("ext-soap"));
+    }
+}
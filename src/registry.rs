@@ -1,15 +1,74 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use serde::{Deserialize, Deserializer, Serialize};
 use anyhow::{Result, Context};
 use std::fs;
-use crate::cache::Cache;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::auth::Auth;
+use crate::cache::{Cache, Validators};
+use crate::repository::matches_name;
+
+type VersionData = serde_json::Map<String, serde_json::Value>;
 
 #[derive(Debug, Deserialize)]
 pub struct PackagistResponse {
-    pub packages: HashMap<String, Vec<PackageVersion>>,
+    pub packages: HashMap<String, Vec<VersionData>>,
+    // "composer/2.0" when each version only lists what changed since the previous one
+    #[serde(default)]
+    pub minified: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+impl PackagistResponse {
+    // the versions of `name`, expanded and parsed
+    pub fn versions(self, name: &str) -> Result<Vec<PackageVersion>> {
+        let mut packages = self.packages;
+        let mut versions = packages.remove(name)
+            .ok_or_else(|| anyhow::anyhow!("Package {} not found", name))?;
+        if self.minified.as_deref() == Some("composer/2.0") {
+            versions = expand_minified(versions);
+        }
+
+        versions.into_iter()
+            .map(|data| {
+                let mut version: PackageVersion = serde_json::from_value(serde_json::Value::Object(data))
+                    .with_context(|| format!("Invalid metadata for {}", name))?;
+                if version.name.is_none() {
+                    version.name = Some(name.to_string());
+                }
+                Ok(version)
+            })
+            .collect()
+    }
+}
+
+// Undo composer's MetadataMinifier: every entry after the first only holds the keys that differ
+// from the entry before it, and "__unset" removes a key that the previous entry had.
+fn expand_minified(versions: Vec<VersionData>) -> Vec<VersionData> {
+    let mut expanded = Vec::with_capacity(versions.len());
+    let mut current: Option<VersionData> = None;
+    for data in versions {
+        let next = match current.take() {
+            None => data,
+            Some(mut previous) => {
+                for (key, value) in data {
+                    if value.as_str() == Some("__unset") {
+                        previous.remove(&key);
+                    } else {
+                        previous.insert(key, value);
+                    }
+                }
+                previous
+            }
+        };
+        expanded.push(next.clone());
+        current = Some(next);
+    }
+    expanded
+}
+
+// Fields are declared in the order composer's ArrayDumper writes them, so composer.lock and
+// installed.json come out key for key like composer's. Links are sorted by name, as composer does.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct PackageVersion {
     pub name: Option<String>,
     pub version: String,
@@ -17,27 +76,133 @@ pub struct PackageVersion {
     #[serde(alias = "version_normalized", default)]
     pub version_normalized: String,
 
-    #[serde(default, deserialize_with = "deserialize_packagist_map")]
-    pub require: HashMap<String, String>,
+    #[serde(default, rename = "target-dir", skip_serializing_if = "Option::is_none")]
+    pub target_dir: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceInfo>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dist: Option<DistInfo>,
+
+    #[serde(default, deserialize_with = "deserialize_packagist_map", skip_serializing_if = "BTreeMap::is_empty")]
+    pub require: BTreeMap<String, String>,
+
+    #[serde(default, deserialize_with = "deserialize_packagist_map", skip_serializing_if = "BTreeMap::is_empty")]
+    pub conflict: BTreeMap<String, String>,
+
+    #[serde(default, deserialize_with = "deserialize_packagist_map", skip_serializing_if = "BTreeMap::is_empty")]
+    pub provide: BTreeMap<String, String>,
+
+    #[serde(default, deserialize_with = "deserialize_packagist_map", skip_serializing_if = "BTreeMap::is_empty")]
+    pub replace: BTreeMap<String, String>,
+
+    #[serde(default, rename = "require-dev", deserialize_with = "deserialize_packagist_map", skip_serializing_if = "BTreeMap::is_empty")]
+    pub require_dev: BTreeMap<String, String>,
+
+    #[serde(default, deserialize_with = "deserialize_packagist_map", skip_serializing_if = "BTreeMap::is_empty")]
+    pub suggest: BTreeMap<String, String>,
+
+    #[serde(default, rename = "default-branch", skip_serializing_if = "std::ops::Not::not")]
+    pub default_branch: bool,
+
+    #[serde(default, deserialize_with = "deserialize_string_list", skip_serializing_if = "Vec::is_empty")]
+    pub bin: Vec<String>,
+
+    // "library", "metapackage", "symfony-bundle", ...
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub package_type: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<serde_json::Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autoload: Option<serde_json::Value>,
+
+    #[serde(default, rename = "autoload-dev", skip_serializing_if = "Option::is_none")]
+    pub autoload_dev: Option<serde_json::Value>,
+
+    #[serde(default, rename = "notification-url", skip_serializing_if = "Option::is_none")]
+    pub notification_url: Option<String>,
+
+    #[serde(default, rename = "include-path", deserialize_with = "deserialize_string_list", skip_serializing_if = "Vec::is_empty")]
+    pub include_path: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<serde_json::Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scripts: Option<serde_json::Value>,
+
+    #[serde(default, deserialize_with = "deserialize_string_list", skip_serializing_if = "Vec::is_empty")]
+    pub license: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authors: Option<serde_json::Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_string_list", skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub support: Option<serde_json::Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub funding: Option<serde_json::Value>,
+
+    // true, or the name of the suggested replacement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abandoned: Option<serde_json::Value>,
+
+    // release date, "2023-02-06T13:44:46+00:00"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+
+    // how path repositories want their packages installed: {"symlink": false}
+    #[serde(default, rename = "transport-options", skip_serializing_if = "Option::is_none")]
+    pub transport_options: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DistInfo {
-    pub url: String,
     pub r#type: String,
+    pub url: String,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub reference: Option<String>, // commit hash
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub shasum: Option<String>,
+
+    // sha256 of the archive as first downloaded, recorded in composer.lock
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirrors: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SourceInfo {
+    pub r#type: String,
+    pub url: String,
+
+    #[serde(default)]
+    pub reference: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirrors: Option<serde_json::Value>,
 }
 
 // Helper fn:
 // Packagist sometimes sends "__unset" (str) instead of {}
 // this handles those instances so the program doesn't crash
-fn deserialize_packagist_map<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+pub fn deserialize_packagist_map<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
 where
     D: Deserializer<'de>, {
 
@@ -47,7 +212,7 @@ where
     match v {
         // std case -> map
         serde_json::Value::Object(obj) => {
-            let mut map = HashMap::new();
+            let mut map = BTreeMap::new();
             for (k, val) in obj {
                 if let serde_json::Value::String(s) = val {
                     map.insert(k, s);
@@ -57,79 +222,363 @@ where
         }
 
         // edge case: packagist says "remove all requirements"
-        serde_json::Value::String(s) if s == "__unset" => Ok(HashMap::new()),
+        serde_json::Value::String(s) if s == "__unset" => Ok(BTreeMap::new()),
 
         // edge case: empty array [] is sometimes sent for empty maps in php
-        serde_json::Value::Array(_) => Ok(HashMap::new()),
+        serde_json::Value::Array(_) => Ok(BTreeMap::new()),
 
         // fallback: null or anything else -> empty map
-        _ => Ok(HashMap::new()),
+        _ => Ok(BTreeMap::new()),
     }
 
 }
+// "MIT" or ["MIT"]: composer.json allows a single string where a list is meant
+fn deserialize_string_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>, {
+    let v: serde_json::Value = Deserialize::deserialize(deserializer)?;
+    Ok(match v {
+        serde_json::Value::String(s) => vec![s],
+        serde_json::Value::Array(items) => items.into_iter()
+            .filter_map(|item| item.as_str().map(String::from))
+            .collect(),
+        _ => Vec::new(),
+    })
+}
+
+pub const PACKAGIST_URL: &str = "https://repo.packagist.org";
+
+// the parts of a composer repository's packages.json we use
+#[derive(Debug, Deserialize)]
+struct PackagesJson {
+    #[serde(default, rename = "metadata-url")]
+    metadata_url: Option<String>,
+    #[serde(default, rename = "available-packages")]
+    available_packages: Option<Vec<String>>,
+    #[serde(default, rename = "available-package-patterns")]
+    available_package_patterns: Option<Vec<String>>,
+    // packages listed inline: {"vendor/pkg": {"1.0.0": {...}}} (or a list of versions)
+    #[serde(default, deserialize_with = "deserialize_inline_packages")]
+    packages: HashMap<String, Vec<VersionData>>,
+}
+
+fn deserialize_inline_packages<'de, D>(deserializer: D) -> Result<HashMap<String, Vec<VersionData>>, D::Error>
+where
+    D: Deserializer<'de>, {
+    let v: serde_json::Value = Deserialize::deserialize(deserializer)?;
+    let mut packages = HashMap::new();
+    if let serde_json::Value::Object(obj) = v {
+        for (name, versions) in obj {
+            let versions: Vec<VersionData> = match versions {
+                serde_json::Value::Object(by_version) => by_version.into_iter()
+                    .filter_map(|(_, data)| data.as_object().cloned())
+                    .collect(),
+                serde_json::Value::Array(list) => list.into_iter()
+                    .filter_map(|data| data.as_object().cloned())
+                    .collect(),
+                _ => continue,
+            };
+            packages.insert(name, versions);
+        }
+    }
+    Ok(packages)
+}
+
+// A composer repository: packagist.org, or any server publishing a packages.json (satis, private packagist)
 pub struct RegistryClient {
     client: reqwest::Client,
-    base_url: String,
+    // "https://repo.packagist.org/p2/%package%.json"; empty when the repository only lists packages inline
+    metadata_url: String,
+    // names (with * wildcards) the repository says it has, if it says
+    available: Option<Vec<String>>,
     cache: Cache,
+    auth: Arc<Auth>,
+    // whether a package's tagged-release file exists, remembered for the branch lookup
+    known: Mutex<HashMap<String, bool>>,
 }
 
 impl RegistryClient {
-    pub fn new() -> Self {
+    pub fn new(auth: Arc<Auth>) -> Self {
+        Self::with_metadata_url(format!("{}/p2/%package%.json", PACKAGIST_URL), Cache::new(), auth)
+    }
+
+    fn with_metadata_url(metadata_url: String, cache: Cache, auth: Arc<Auth>) -> Self {
         Self {
             client: reqwest::Client::builder()
                 .user_agent("Maestro/0.1")
+                .redirect(crate::auth::redirect_policy())
                 .build()
                 .unwrap(),
-            base_url: "https://repo.packagist.org/p2".to_string(),
-            cache: Cache::new(),
+            metadata_url,
+            available: None,
+            cache,
+            auth,
+            known: Mutex::new(HashMap::new()),
         }
     }
 
-    pub async fn get_package_metadata(&self, name: &str) -> Result<Vec<PackageVersion>> {
+    // Read a repository's packages.json: where per-package metadata lives, and any packages listed inline.
+    pub async fn from_packages_json(url: &str, auth: Arc<Auth>) -> Result<(Self, Vec<PackageVersion>)> {
+        let url = url.trim_end_matches('/');
+        let mut registry = Self::with_metadata_url(String::new(), Cache::for_repository(url), auth);
 
-        // check cache
-        let cache_path = self.cache.get_metadata_path(name);
+        let cache_path = registry.cache.get_metadata_path("packages");
+        let text = registry.fetch(&format!("{}/packages.json", url), &cache_path).await?
+            .ok_or_else(|| anyhow::anyhow!("{} has no packages.json", url))?;
+        let root: PackagesJson = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid packages.json in {}", url))?;
 
-        if cache_path.exists() {
-            if let Ok(content) = fs::read_to_string(&cache_path) {
-                if let Ok(parsed) = serde_json::from_str::<PackagistResponse>(&content) {
-                    if let Some(versions) = parsed.packages.get(name) {
-                        return Ok(versions.clone());
-                    }
-                }
+        if let Some(metadata_url) = root.metadata_url {
+            registry.metadata_url = absolute_url(url, &metadata_url);
+        }
+        if root.available_packages.is_some() || root.available_package_patterns.is_some() {
+            let mut available = root.available_packages.unwrap_or_default();
+            available.extend(root.available_package_patterns.unwrap_or_default());
+            registry.available = Some(available);
+        }
+
+        let mut inline = Vec::new();
+        for (name, versions) in root.packages {
+            let response = PackagistResponse { packages: HashMap::from([(name.clone(), versions)]), minified: None };
+            inline.extend(response.versions(&name)?);
+        }
+        Ok((registry, inline))
+    }
+
+    // Versions of `name`: tagged releases, or with `branches` the dev branches packagist keeps in a
+    // separate "~dev" file. None means the repository doesn't have the package at all.
+    pub async fn find(&self, name: &str, branches: bool) -> Result<Option<Vec<PackageVersion>>> {
+        if self.metadata_url.is_empty() {
+            return Ok(None);
+        }
+        if let Some(available) = &self.available
+            && !available.iter().any(|pattern| matches_name(pattern, name))
+        {
+            return Ok(None);
+        }
+
+        if !branches {
+            let versions = self.find_file(name, name, self.cache.get_metadata_path(name)).await?;
+            self.known.lock().unwrap().insert(name.to_string(), versions.is_some());
+            return Ok(versions);
+        }
+
+        let file = format!("{}~dev", name);
+        let versions = self.find_file(name, &file, self.cache.get_dev_metadata_path(name)).await?;
+        let known = self.known.lock().unwrap().get(name).copied();
+        let known = match known {
+            Some(known) => known,
+            None => self.find_file(name, name, self.cache.get_metadata_path(name)).await?.is_some(),
+        };
+        Ok(match versions {
+            Some(versions) => Some(versions),
+            None if known => Some(Vec::new()),
+            None => None,
+        })
+    }
+
+    async fn find_file(&self, name: &str, file: &str, cache_path: PathBuf) -> Result<Option<Vec<PackageVersion>>> {
+        let url = self.metadata_url.replace("%package%", file);
+        match self.fetch(&url, &cache_path).await? {
+            Some(text) => Ok(Some(parse_metadata(&text, name)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Cached files are revalidated with If-None-Match/If-Modified-Since, so new releases show up
+    // without downloading unchanged files again; when the server can't be reached the cached copy is used.
+    // None when the file doesn't exist (404).
+    async fn fetch(&self, url: &str, cache_path: &Path) -> Result<Option<String>> {
+        let cached = fs::read_to_string(cache_path).ok();
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        if cached.is_some() {
+            let validators = self.cache.read_validators(cache_path);
+            if let Some(etag) = validators.etag.as_deref().and_then(|v| v.parse().ok()) {
+                headers.insert(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = validators.last_modified.as_deref().and_then(|v| v.parse().ok()) {
+                headers.insert(reqwest::header::IF_MODIFIED_SINCE, last_modified);
             }
         }
 
-        let url = format!("{}/{}.json", self.base_url, name);
+        let resp = match self.auth.get(&self.client, url, headers).await {
+            Ok(resp) => resp,
+            Err(e) => match cached {
+                Some(content) => return Ok(Some(content)),
+                None => return Err(e).with_context(|| format!("Failed to connect to {}", url)),
+            },
+        };
+
+        if resp.status() == reqwest::StatusCode::NOT_MODIFIED && cached.is_some() {
+            return Ok(cached);
+        }
 
-        let resp = self.client.get(&url)
-            .send()
-            .await
-            .context("Failed to connect to Packagist")?;
+        // unknown names are not an error here: they may be virtual packages satisfied by "provide"
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
 
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED || resp.status() == reqwest::StatusCode::FORBIDDEN {
+            anyhow::bail!("{} returned {}, check the credentials for this host in auth.json", url, resp.status());
+        }
         if !resp.status().is_success() {
-            anyhow::bail!("Packagist returned error: {}", resp.status());
+            anyhow::bail!("{} returned error: {}", url, resp.status());
         }
 
+        let header = |name: reqwest::header::HeaderName| {
+            resp.headers().get(name).and_then(|v| v.to_str().ok()).map(String::from)
+        };
+        let validators = Validators {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        };
+
         let text = resp.text().await?;
 
         // Write to cache
-        if let Err(e) = fs::write(&cache_path, &text) {
+        if let Err(e) = fs::write(cache_path, &text) {
             eprintln!("Warning: Failed to write cache: {}", e);
         }
+        self.cache.write_validators(cache_path, &validators);
 
-        let parsed: PackagistResponse = serde_json::from_str(&text)?;
+        Ok(Some(text))
+    }
+}
 
-        let mut versions = parsed.packages
-            .get(name)
-            .cloned()
-            .ok_or_else(|| anyhow::anyhow!("Package {} not found", name))?;
+// "/p2/%package%.json" is relative to the repository's host, like composer's canonicalizeUrl()
+fn absolute_url(base: &str, url: &str) -> String {
+    if !url.starts_with('/') {
+        return url.to_string();
+    }
+    let host_end = base.find("://")
+        .and_then(|scheme| base[scheme + 3..].find('/').map(|i| scheme + 3 + i))
+        .unwrap_or(base.len());
+    format!("{}{}", &base[..host_end], url)
+}
 
-        for v in &mut versions {
-            if v.name.is_none() {
-                v.name = Some(name.to_string());
-            }
+fn parse_metadata(text: &str, name: &str) -> Result<Vec<PackageVersion>> {
+    let parsed: PackagistResponse = serde_json::from_str(text)?;
+    parsed.versions(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str, text: &str) -> Vec<PackageVersion> {
+        serde_json::from_str::<PackagistResponse>(text).unwrap().versions(name).unwrap()
+    }
+
+    fn dist_url(version: &PackageVersion) -> Option<&str> {
+        version.dist.as_ref().map(|d| d.url.as_str())
+    }
+
+    #[test]
+    fn expands_minified_psr_log() {
+        let versions = fixture("psr/log", include_str!("../tests/fixtures/p2/psr-log.json"));
+        let listed: Vec<&str> = versions.iter().map(|v| v.version.as_str()).collect();
+        assert_eq!(listed, ["3.0.2", "3.0.1", "2.0.0", "1.1.4", "1.0.0"]);
+        assert!(versions.iter().all(|v| v.name.as_deref() == Some("psr/log")));
+
+        // fields not repeated in an entry carry over from the one before it
+        assert_eq!(versions[1].require.get("php").map(String::as_str), Some(">=8.0.0"));
+        assert_eq!(versions[2].require.get("php").map(String::as_str), Some(">=8.0.0"));
+        assert_eq!(versions[3].require.get("php").map(String::as_str), Some(">=5.3.0"));
+        assert_eq!(
+            dist_url(&versions[1]),
+            Some("https://api.github.com/repos/php-fig/log/zipball/79dff0b268932c640297f5208d6298f71855c03e"),
+        );
+        assert_eq!(versions[2].version_normalized, "2.0.0.0");
+
+        // "__unset" drops them again
+        assert!(versions[4].require.is_empty());
+        assert!(dist_url(&versions[4]).is_some());
+    }
+
+    #[test]
+    fn expands_minified_monolog() {
+        let versions = fixture("monolog/monolog", include_str!("../tests/fixtures/p2/monolog-monolog.json"));
+        let listed: Vec<&str> = versions.iter().map(|v| v.version.as_str()).collect();
+        assert_eq!(listed, ["3.7.0", "2.9.3", "1.27.1"]);
+        assert!(versions.iter().all(|v| v.dist.is_some() && !v.default_branch));
+
+        let v2 = &versions[1];
+        assert_eq!(v2.require.get("psr/log").map(String::as_str), Some("^1.0.1 || ^2.0 || ^3.0"));
+        assert_eq!(v2.provide.get("psr/log-implementation").map(String::as_str), Some("1.0.0 || 2.0.0 || 3.0.0"));
+        assert_eq!(v2.suggest.len(), 2);
+        assert_eq!(v2.autoload, versions[0].autoload);
+
+        let v1 = &versions[2];
+        assert!(v1.suggest.is_empty());
+        assert_eq!(v1.require.get("psr/log").map(String::as_str), Some("~1.0"));
+        assert!(dist_url(v1).is_some_and(|url| url.ends_with("904713c5929655dc9b97288b69cfeedad610c9a1")));
+    }
+
+    #[test]
+    fn expands_dev_branches() {
+        let versions = fixture("monolog/monolog", include_str!("../tests/fixtures/p2/monolog-monolog~dev.json"));
+        let listed: Vec<(&str, &str, bool)> = versions.iter()
+            .map(|v| (v.version.as_str(), v.version_normalized.as_str(), v.default_branch))
+            .collect();
+        assert_eq!(listed, [
+            ("dev-main", "dev-main", true),
+            ("2.x-dev", "2.9999999.9999999.9999999-dev", false),
+        ]);
+        assert_eq!(versions[1].require.get("php").map(String::as_str), Some(">=7.2"));
+        assert_eq!(versions[1].extra.as_ref().unwrap()["branch-alias"]["dev-main"], "2.x-dev");
+    }
+
+    #[test]
+    fn leaves_unminified_metadata_alone() {
+        let text = r#"{"packages":{"a/b":[
+            {"version":"2.0.0","require":{"c/d":"^1.0"}},
+            {"version":"1.0.0"}
+        ]}}"#;
+        let versions = fixture("a/b", text);
+        assert_eq!(versions[0].require.len(), 1);
+        assert!(versions[1].require.is_empty());
+    }
+
+    #[tokio::test]
+    async fn revalidates_cached_metadata() {
+        let response = |status: &str, headers: &str, body: &str| format!(
+            "HTTP/1.1 {}\r\n{}content-length: {}\r\nconnection: close\r\n\r\n{}", status, headers, body.len(), body,
+        );
+        let (port, requests) = crate::auth::tests::serve(vec![
+            response("200 OK", "etag: \"v1\"\r\nlast-modified: Mon, 01 Jan 2024 00:00:00 GMT\r\n", "first"),
+            response("304 Not Modified", "", ""),
+            response("200 OK", "etag: \"v2\"\r\nlast-modified: Tue, 02 Jan 2024 00:00:00 GMT\r\n", "second"),
+        ]);
+        let cache_path = std::env::temp_dir().join(format!("maestro-revalidate-{}.json", std::process::id()));
+        let registry = RegistryClient::with_metadata_url(String::new(), Cache::new(), Arc::new(Auth::default()));
+        let url = format!("http://127.0.0.1:{}/p2/a/b.json", port);
+
+        assert_eq!(registry.fetch(&url, &cache_path).await.unwrap().as_deref(), Some("first"));
+        let validators = registry.cache.read_validators(&cache_path);
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(validators.last_modified.as_deref(), Some("Mon, 01 Jan 2024 00:00:00 GMT"));
+
+        // not modified: the cached body is used as it is
+        assert_eq!(registry.fetch(&url, &cache_path).await.unwrap().as_deref(), Some("first"));
+        assert_eq!(fs::read_to_string(&cache_path).unwrap(), "first");
+
+        // modified: body and validators are both replaced
+        assert_eq!(registry.fetch(&url, &cache_path).await.unwrap().as_deref(), Some("second"));
+        assert_eq!(fs::read_to_string(&cache_path).unwrap(), "second");
+        let validators = registry.cache.read_validators(&cache_path);
+        assert_eq!(validators.etag.as_deref(), Some("\"v2\""));
+        assert_eq!(validators.last_modified.as_deref(), Some("Tue, 02 Jan 2024 00:00:00 GMT"));
+
+        let requests = requests.join().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(!requests[0].contains("if-modified-since"));
+        for request in &requests[1..] {
+            assert!(request.contains("if-none-match: \"v1\"\r\n"), "{}", request);
+            assert!(request.contains("if-modified-since: mon, 01 jan 2024 00:00:00 gmt\r\n"), "{}", request);
         }
-        Ok(versions)
+
+        fs::remove_file(&cache_path).ok();
+        registry.cache.write_validators(&cache_path, &Validators::default());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,630 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use crate::auth::Auth;
+use crate::cache::Cache;
+use crate::manifest::ComposerManifest;
+use crate::registry::{PackageVersion, RegistryClient, PACKAGIST_URL};
+use crate::version::{branch_version, normalize, PhpVersion};
+
+// Where packages come from: the "repositories" of composer.json in the order they are listed,
+// followed by packagist.org unless it was disabled with {"packagist.org": false}.
+pub struct RepositorySet {
+    repositories: Vec<Repository>,
+}
+
+struct Repository {
+    source: Source,
+    // once a canonical repository has a package, lower priority repositories are not asked for it
+    canonical: bool,
+    // package names (with * wildcards) this repository is limited to / never used for
+    only: Vec<String>,
+    exclude: Vec<String>,
+}
+
+enum Source {
+    Composer(RegistryClient),
+    // every version is known once loaded: package, path, artifact and vcs repositories
+    Listed(HashMap<String, Vec<PackageVersion>>),
+}
+
+impl Repository {
+    fn new(source: Source, config: &Map<String, Value>) -> Self {
+        let names = |key: &str| -> Vec<String> {
+            config.get(key).and_then(|v| v.as_array()).into_iter().flatten()
+                .filter_map(|v| v.as_str().map(|s| s.to_lowercase()))
+                .collect()
+        };
+        Self {
+            source,
+            canonical: config.get("canonical").and_then(|v| v.as_bool()).unwrap_or(true),
+            only: names("only"),
+            exclude: names("exclude"),
+        }
+    }
+
+    fn allows(&self, name: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|pattern| matches_name(pattern, name)))
+            && !self.exclude.iter().any(|pattern| matches_name(pattern, name))
+    }
+
+    // None when the repository doesn't have the package
+    async fn find(&self, name: &str, branches: bool) -> Result<Option<Vec<PackageVersion>>> {
+        match &self.source {
+            Source::Composer(registry) => registry.find(name, branches).await,
+            // branches came along with everything else
+            Source::Listed(packages) => Ok(packages.get(name)
+                .map(|versions| if branches { Vec::new() } else { versions.clone() })),
+        }
+    }
+}
+
+impl RepositorySet {
+    pub async fn from_manifest(manifest: &ComposerManifest, auth: &Arc<Auth>) -> Result<Self> {
+        // "repositories" is a list, or an object whose keys are just labels
+        let entries: Vec<(Option<String>, Value)> = match &manifest.repositories {
+            Some(Value::Array(list)) => list.iter().map(|v| (None, v.clone())).collect(),
+            Some(Value::Object(map)) => map.iter().map(|(k, v)| (Some(k.clone()), v.clone())).collect(),
+            _ => Vec::new(),
+        };
+
+        let mut repositories = Vec::new();
+        let mut packagist = true;
+        for (key, entry) in entries {
+            // {"packagist.org": false} in a list, or "packagist.org": false as an object entry
+            if key.as_deref().is_some_and(is_packagist_key) && entry == Value::Bool(false) {
+                packagist = false;
+                continue;
+            }
+            let Value::Object(config) = entry else { continue; };
+            if config.len() == 1 && config.iter().all(|(k, v)| is_packagist_key(k) && *v == Value::Bool(false)) {
+                packagist = false;
+                continue;
+            }
+
+            let kind = config.get("type").and_then(|v| v.as_str()).unwrap_or_default();
+            let url = config.get("url").and_then(|v| v.as_str()).unwrap_or_default();
+            match kind {
+                "composer" if is_packagist_url(url) => {
+                    repositories.push(Repository::new(Source::Composer(RegistryClient::new(auth.clone())), &config));
+                    packagist = false;
+                }
+                "composer" => {
+                    let (registry, inline) = RegistryClient::from_packages_json(url, auth.clone()).await?;
+                    repositories.push(Repository::new(Source::Composer(registry), &config));
+                    if !inline.is_empty() {
+                        repositories.push(Repository::new(Source::Listed(by_name(inline)), &config));
+                    }
+                }
+                "package" => repositories.push(Repository::new(Source::Listed(by_name(load_inline(&config)?)), &config)),
+                "path" => repositories.push(Repository::new(Source::Listed(by_name(load_path(url, &config)?)), &config)),
+                "artifact" => repositories.push(Repository::new(Source::Listed(by_name(load_artifact(url)?)), &config)),
+                "vcs" | "git" | "github" | "gitlab" | "bitbucket" | "git-bitbucket" => {
+                    repositories.push(Repository::new(Source::Listed(by_name(load_vcs(url)?)), &config));
+                }
+                other => {
+                    eprintln!("{} repositories of type \"{}\" are not supported, skipping {}", "Warning:".yellow().bold(), other, url);
+                }
+            }
+        }
+
+        if packagist {
+            repositories.push(Repository::new(Source::Composer(RegistryClient::new(auth.clone())), &Map::new()));
+        }
+        Ok(Self { repositories })
+    }
+
+    // Every version of `name` the repositories offer, highest priority first. With `branches`,
+    // only the dev branches that composer repositories keep apart from tagged releases.
+    pub async fn find(&self, name: &str, branches: bool) -> Result<Vec<PackageVersion>> {
+        let mut found: Vec<PackageVersion> = Vec::new();
+        for repository in &self.repositories {
+            if !repository.allows(name) {
+                continue;
+            }
+            let Some(versions) = repository.find(name, branches).await? else { continue; };
+            for version in versions {
+                if !found.iter().any(|f| f.version_normalized == version.version_normalized) {
+                    found.push(version);
+                }
+            }
+            if repository.canonical {
+                break;
+            }
+        }
+        Ok(found)
+    }
+}
+
+fn is_packagist_key(key: &str) -> bool {
+    key == "packagist.org" || key == "packagist"
+}
+
+fn is_packagist_url(url: &str) -> bool {
+    let url = url.trim_end_matches('/');
+    url == PACKAGIST_URL || url == "https://packagist.org"
+}
+
+// package names may contain * wildcards, matched case-insensitively
+pub fn matches_name(pattern: &str, name: &str) -> bool {
+    wildcard(&pattern.to_lowercase(), &name.to_lowercase())
+}
+
+// '*' matches any run of characters, '?' a single one
+fn wildcard(pattern: &str, text: &str) -> bool {
+    let mut chars = pattern.chars();
+    match chars.next() {
+        None => text.is_empty(),
+        Some('*') => text.char_indices().map(|(i, _)| i).chain([text.len()])
+            .any(|i| wildcard(chars.as_str(), &text[i..])),
+        Some(c) => {
+            let mut rest = text.chars();
+            rest.next().is_some_and(|t| c == '?' || c == t) && wildcard(chars.as_str(), rest.as_str())
+        }
+    }
+}
+
+// group versions by (lowercased) package name, filling in version_normalized
+fn by_name(versions: Vec<PackageVersion>) -> HashMap<String, Vec<PackageVersion>> {
+    let mut packages: HashMap<String, Vec<PackageVersion>> = HashMap::new();
+    for mut version in versions {
+        let Some(name) = version.name.as_ref().map(|n| n.to_lowercase()) else { continue; };
+        if version.version_normalized.is_empty() {
+            match normalize(&version.version) {
+                Ok(normalized) => version.version_normalized = normalized,
+                Err(_) => {
+                    eprintln!("{} {} has an invalid version \"{}\", skipping it", "Warning:".yellow().bold(), name, version.version);
+                    continue;
+                }
+            }
+        }
+        version.name = Some(name.clone());
+        packages.entry(name).or_default().push(version);
+    }
+    packages
+}
+
+// a composer.json (or inline package definition) as package metadata
+fn package_from(data: Map<String, Value>, origin: &str) -> Result<PackageVersion> {
+    if !data.get("name").is_some_and(|v| v.is_string()) {
+        anyhow::bail!("Package in {} has no name", origin);
+    }
+    if !data.get("version").is_some_and(|v| v.is_string()) {
+        anyhow::bail!("Package in {} has no version", origin);
+    }
+    serde_json::from_value(Value::Object(data)).with_context(|| format!("Invalid package in {}", origin))
+}
+
+// {"type": "package", "package": {...}} or a list of them
+fn load_inline(config: &Map<String, Value>) -> Result<Vec<PackageVersion>> {
+    let definitions = match config.get("package") {
+        Some(Value::Array(list)) => list.clone(),
+        Some(definition @ Value::Object(_)) => vec![definition.clone()],
+        _ => anyhow::bail!("A \"package\" repository needs a \"package\" definition"),
+    };
+    definitions.into_iter()
+        .map(|definition| match definition {
+            Value::Object(data) => package_from(data, "a package repository"),
+            _ => anyhow::bail!("Invalid definition in a package repository"),
+        })
+        .collect()
+}
+
+// Local directories (the url may contain * wildcards) installed by symlink or copy.
+// The version comes from options.versions, the composer.json, the checked out git tag/branch, or "dev-main".
+fn load_path(url: &str, config: &Map<String, Value>) -> Result<Vec<PackageVersion>> {
+    let mut options = config.get("options").and_then(|v| v.as_object()).cloned().unwrap_or_default();
+    let versions = options.remove("versions").and_then(|v| v.as_object().cloned()).unwrap_or_default();
+
+    let mut packages = Vec::new();
+    for dir in expand_glob(url) {
+        let Ok(content) = fs::read_to_string(dir.join("composer.json")) else { continue; };
+        let mut data: Map<String, Value> = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid composer.json in {}", dir.display()))?;
+        let Some(name) = data.get("name").and_then(|v| v.as_str()).map(|s| s.to_lowercase()) else {
+            eprintln!("{} {} has no package name, skipping it", "Warning:".yellow().bold(), dir.display());
+            continue;
+        };
+
+        let version = match versions.get(&name).and_then(|v| v.as_str()) {
+            Some(version) => version.to_string(),
+            None => match data.get("version").and_then(|v| v.as_str()) {
+                Some(version) => version.to_string(),
+                None => guess_version(&dir).unwrap_or_else(|| "dev-main".to_string()),
+            },
+        };
+        data.insert("version".into(), json!(version));
+        data.insert("dist".into(), json!({
+            "type": "path",
+            "url": dir.to_string_lossy(),
+            "reference": hex::encode(Sha256::digest(content.as_bytes())),
+        }));
+        if !options.is_empty() {
+            data.insert("transport-options".into(), Value::Object(options.clone()));
+        }
+        packages.push(package_from(data, &dir.to_string_lossy())?);
+    }
+
+    if packages.is_empty() {
+        eprintln!("{} the path repository {} contains no packages", "Warning:".yellow().bold(), url);
+    }
+    Ok(packages)
+}
+
+// the checked out tag, or branch, of a git working copy
+pub fn guess_version(dir: &Path) -> Option<String> {
+    let git = |args: &[&str]| -> Option<String> {
+        let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !text.is_empty()).then_some(text)
+    };
+    if let Some(tag) = git(&["describe", "--exact-match", "--tags"])
+        && PhpVersion::parse(&tag).is_ok()
+    {
+        return Some(tag);
+    }
+    git(&["symbolic-ref", "--short", "-q", "HEAD"]).map(|branch| branch_version(&branch))
+}
+
+// the commit a git working copy has checked out
+pub fn head_reference(dir: &Path) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(["rev-parse", "HEAD"]).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !text.is_empty()).then_some(text)
+}
+
+// "../packages/*" -> every matching directory
+fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::new()];
+    for component in Path::new(pattern).components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !part.contains(['*', '?']) {
+            paths = paths.into_iter().map(|base| base.join(component)).collect();
+            continue;
+        }
+        paths = paths.into_iter()
+            .flat_map(|base| {
+                let dir = if base.as_os_str().is_empty() { PathBuf::from(".") } else { base.clone() };
+                let mut matches: Vec<PathBuf> = fs::read_dir(dir).into_iter().flatten().flatten()
+                    .filter(|entry| entry.path().is_dir())
+                    .filter(|entry| wildcard(&part, &entry.file_name().to_string_lossy()))
+                    .map(|entry| base.join(entry.file_name()))
+                    .collect();
+                matches.sort();
+                matches
+            })
+            .collect();
+    }
+    paths
+}
+
+// A directory of zip files, each with a composer.json (at the root or one directory down)
+// that names the package and its version.
+fn load_artifact(url: &str) -> Result<Vec<PackageVersion>> {
+    let dir = fs::canonicalize(url).with_context(|| format!("Artifact directory {} not found", url))?;
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)?.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "zip"))
+        .collect();
+    files.sort();
+
+    let mut packages = Vec::new();
+    for file in files {
+        let Some(mut data) = read_zipped_manifest(&file)? else {
+            eprintln!("{} {} contains no composer.json, skipping it", "Warning:".yellow().bold(), file.display());
+            continue;
+        };
+        data.insert("dist".into(), json!({ "type": "zip", "url": file.to_string_lossy() }));
+        packages.push(package_from(data, &file.to_string_lossy())?);
+    }
+    Ok(packages)
+}
+
+fn read_zipped_manifest(file: &Path) -> Result<Option<Map<String, Value>>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(file)?)
+        .with_context(|| format!("Failed to read zip archive {}", file.display()))?;
+
+    let mut candidates: Vec<String> = archive.file_names()
+        .filter(|name| *name == "composer.json" || (name.ends_with("/composer.json") && name.matches('/').count() == 1))
+        .map(String::from)
+        .collect();
+    candidates.sort_by_key(|name| name.len());
+    let Some(name) = candidates.first() else { return Ok(None); };
+
+    let mut content = String::new();
+    archive.by_name(name)?.read_to_string(&mut content)?;
+    let data = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid composer.json in {}", file.display()))?;
+    Ok(Some(data))
+}
+
+// A git repository: every tag that is a valid version and every branch with a composer.json.
+// The repository is mirrored into the cache and fetched again on each load.
+fn load_vcs(url: &str) -> Result<Vec<PackageVersion>> {
+    let mirror = Cache::new().get_vcs_path(url);
+    if mirror.exists() {
+        let updated = Command::new("git").arg("--git-dir").arg(&mirror)
+            .args(["remote", "update", "--prune"])
+            .output();
+        if !updated.is_ok_and(|output| output.status.success()) {
+            eprintln!("{} could not update {}, using the cached copy", "Warning:".yellow().bold(), url);
+        }
+    } else {
+        if let Some(parent) = mirror.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let output = Command::new("git").args(["clone", "--mirror", "--quiet", url]).arg(&mirror)
+            .output()
+            .context("Failed to run git")?;
+        if !output.status.success() {
+            anyhow::bail!("Failed to clone {}: {}", url, String::from_utf8_lossy(&output.stderr).trim());
+        }
+    }
+
+    let git = |args: &[&str]| -> Option<String> {
+        let output = Command::new("git").arg("--git-dir").arg(&mirror).args(args).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let refs = git(&["for-each-ref", "--format=%(objectname)%09%(*objectname)%09%(refname)", "refs/tags", "refs/heads"])
+        .ok_or_else(|| anyhow::anyhow!("Failed to list the refs of {}", url))?;
+
+    let mut packages = Vec::new();
+    for line in refs.lines() {
+        let mut fields = line.split('\t');
+        let (Some(object), Some(peeled), Some(refname)) = (fields.next(), fields.next(), fields.next()) else { continue; };
+        // annotated tags point at a tag object; the commit is the peeled one
+        let commit = if peeled.is_empty() { object } else { peeled };
+
+        let version = if let Some(tag) = refname.strip_prefix("refs/tags/") {
+            if PhpVersion::parse(tag).is_err() {
+                continue;
+            }
+            tag.to_string()
+        } else if let Some(branch) = refname.strip_prefix("refs/heads/") {
+            branch_version(branch)
+        } else {
+            continue;
+        };
+
+        let Some(content) = git(&["show", &format!("{}:composer.json", commit)]) else { continue; };
+        let Ok(mut data) = serde_json::from_str::<Map<String, Value>>(&content) else { continue; };
+        data.insert("version".into(), json!(version));
+        data.insert("source".into(), json!({ "type": "git", "url": url, "reference": commit }));
+        if let Some(zipball) = github_zipball(url, commit) {
+            data.insert("dist".into(), json!({ "type": "zip", "url": zipball, "reference": commit, "shasum": "" }));
+        }
+        match package_from(data, &format!("{} ({})", url, refname)) {
+            Ok(package) => packages.push(package),
+            Err(e) => eprintln!("{} {}", "Warning:".yellow().bold(), e),
+        }
+    }
+    Ok(packages)
+}
+
+// github serves archives of any commit, which saves cloning at install time
+fn github_zipball(url: &str, commit: &str) -> Option<String> {
+    let path = url.strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("git@github.com:"))?
+        .trim_end_matches('/')
+        .trim_end_matches(".git");
+    let (owner, repo) = path.split_once('/')?;
+    Some(format!("https://api.github.com/repos/{}/{}/zipball/{}", owner, repo, commit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn manifest(repositories: Value) -> ComposerManifest {
+        serde_json::from_value(json!({ "repositories": repositories })).unwrap()
+    }
+
+    async fn repositories(repositories: Value) -> RepositorySet {
+        RepositorySet::from_manifest(&manifest(repositories), &Arc::new(Auth::default())).await.unwrap()
+    }
+
+    fn inline(name: &str, version: &str) -> Value {
+        json!({ "name": name, "version": version })
+    }
+
+    async fn versions(set: &RepositorySet, name: &str) -> Vec<String> {
+        set.find(name, false).await.unwrap().into_iter().map(|v| v.version).collect()
+    }
+
+    fn run(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git").arg("-C").arg(dir)
+            .args(["-c", "user.name=Maestro", "-c", "user.email=maestro@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn stops_at_canonical_repositories() {
+        let listing = |canonical: bool| json!([
+            { "type": "package", "canonical": canonical, "package": [inline("acme/lib", "1.0.0"), inline("acme/lib", "1.1.0")] },
+            { "type": "package", "package": [inline("acme/lib", "1.1.0"), inline("acme/lib", "2.0.0"), inline("acme/other", "1.0.0")] },
+            { "packagist.org": false },
+        ]);
+
+        let canonical = repositories(listing(true)).await;
+        assert_eq!(versions(&canonical, "acme/lib").await, ["1.0.0", "1.1.0"]);
+        // a name the first repository doesn't have is still looked up further down
+        assert_eq!(versions(&canonical, "acme/other").await, ["1.0.0"]);
+
+        // the first repository's 1.1.0 wins over the second's
+        let fallthrough = repositories(listing(false)).await;
+        assert_eq!(versions(&fallthrough, "acme/lib").await, ["1.0.0", "1.1.0", "2.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn limits_repositories_to_only_and_exclude() {
+        let set = repositories(json!([
+            {
+                "type": "package",
+                "only": ["acme/*", "other/tool"],
+                "exclude": ["acme/secret-*"],
+                "package": [inline("acme/lib", "1.0.0"), inline("acme/secret-sauce", "1.0.0"), inline("other/tool", "1.0.0"), inline("other/lib", "1.0.0")],
+            },
+            { "type": "package", "package": [inline("acme/secret-sauce", "2.0.0"), inline("other/lib", "2.0.0")] },
+            { "packagist.org": false },
+        ])).await;
+
+        assert_eq!(versions(&set, "acme/lib").await, ["1.0.0"]);
+        assert_eq!(versions(&set, "other/tool").await, ["1.0.0"]);
+        assert_eq!(versions(&set, "acme/secret-sauce").await, ["2.0.0"]);
+        assert_eq!(versions(&set, "other/lib").await, ["2.0.0"]);
+        assert!(matches_name("acme/*-bundle", "acme/mail-bundle"));
+        assert!(!matches_name("acme/*-bundle", "acme/mail"));
+    }
+
+    #[tokio::test]
+    async fn adds_packagist_unless_disabled() {
+        let packagist_last = |set: &RepositorySet| matches!(set.repositories.last().map(|r| &r.source), Some(Source::Composer(_)));
+        let package = json!({ "type": "package", "package": inline("acme/lib", "1.0.0") });
+
+        let set = repositories(json!([package])).await;
+        assert_eq!(set.repositories.len(), 2);
+        assert!(packagist_last(&set));
+
+        for disabled in [json!([package, { "packagist.org": false }]), json!({ "acme": package, "packagist.org": false })] {
+            let set = repositories(disabled).await;
+            assert_eq!(set.repositories.len(), 1);
+            assert!(!packagist_last(&set));
+        }
+    }
+
+    #[tokio::test]
+    async fn loads_inline_packages() {
+        let set = repositories(json!([
+            {
+                "type": "package",
+                "package": {
+                    "name": "Acme/Legacy",
+                    "version": "1.2",
+                    "dist": { "type": "zip", "url": "https://example.com/legacy-1.2.zip" },
+                    "autoload": { "classmap": ["src/"] },
+                },
+            },
+            { "packagist.org": false },
+        ])).await;
+
+        let found = set.find("acme/legacy", false).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name.as_deref(), Some("acme/legacy"));
+        assert_eq!(found[0].version_normalized, "1.2.0.0");
+        assert_eq!(found[0].dist.as_ref().unwrap().url, "https://example.com/legacy-1.2.zip");
+        // branches came along with the rest
+        assert!(set.find("acme/legacy", true).await.unwrap().is_empty());
+
+        assert!(load_inline(&Map::new()).is_err());
+        let nameless = json!({ "package": { "version": "1.0.0" } });
+        assert!(load_inline(nameless.as_object().unwrap()).is_err());
+    }
+
+    #[tokio::test]
+    async fn loads_path_repositories_by_glob() {
+        let root = std::env::temp_dir().join(format!("maestro-path-{}", std::process::id()));
+        let packages = root.join("packages");
+        for (dir, manifest) in [
+            ("explicit", json!({ "name": "acme/explicit", "version": "3.0.0" })),
+            ("optioned", json!({ "name": "acme/optioned", "version": "3.0.0" })),
+            ("plain", json!({ "name": "acme/plain" })),
+            ("tagged", json!({ "name": "acme/tagged" })),
+            ("branched", json!({ "name": "acme/branched" })),
+        ] {
+            fs::create_dir_all(packages.join(dir)).unwrap();
+            fs::write(packages.join(dir).join("composer.json"), manifest.to_string()).unwrap();
+        }
+        fs::create_dir_all(packages.join("empty")).unwrap();
+        fs::create_dir_all(root.join("other/acme-elsewhere")).unwrap();
+        fs::write(root.join("other/acme-elsewhere/composer.json"), json!({ "name": "acme/elsewhere" }).to_string()).unwrap();
+
+        let mut commits = HashMap::new();
+        for (dir, checkout) in [("tagged", ["tag", "v1.4.0"]), ("branched", ["branch", "feature/login"])] {
+            let dir = packages.join(dir);
+            run(&dir, &["init", "--quiet", "--initial-branch=main"]);
+            run(&dir, &["add", "."]);
+            run(&dir, &["commit", "--quiet", "-m", "first"]);
+            run(&dir, &checkout);
+            commits.insert(dir.clone(), run(&dir, &["rev-parse", "HEAD"]));
+        }
+        run(&packages.join("branched"), &["checkout", "--quiet", "feature/login"]);
+
+        let set = repositories(json!([
+            {
+                "type": "path",
+                "url": packages.join("*").to_string_lossy(),
+                "options": { "symlink": false, "versions": { "acme/optioned": "4.1.0" } },
+            },
+            { "packagist.org": false },
+        ])).await;
+
+        assert_eq!(versions(&set, "acme/explicit").await, ["3.0.0"]);
+        assert_eq!(versions(&set, "acme/optioned").await, ["4.1.0"]);
+        assert_eq!(versions(&set, "acme/plain").await, ["dev-main"]);
+        assert_eq!(versions(&set, "acme/tagged").await, ["v1.4.0"]);
+        assert_eq!(versions(&set, "acme/branched").await, ["dev-feature/login"]);
+        assert!(versions(&set, "acme/elsewhere").await.is_empty());
+
+        let plain = &set.find("acme/plain", false).await.unwrap()[0];
+        let dist = plain.dist.as_ref().unwrap();
+        assert_eq!(dist.r#type, "path");
+        assert_eq!(Path::new(&dist.url), packages.join("plain"));
+        // the versions option is not a transport option
+        let options = plain.transport_options.as_ref().unwrap();
+        assert_eq!(options.get("symlink"), Some(&json!(false)));
+        assert!(options.get("versions").is_none());
+
+        for (dir, commit) in &commits {
+            assert_eq!(head_reference(dir).as_ref(), Some(commit));
+        }
+        assert_eq!(guess_version(&packages.join("plain")), None);
+        assert_eq!(head_reference(&packages.join("plain")), None);
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[tokio::test]
+    async fn reads_artifacts_metadata_from_their_composer_json() {
+        let dir = std::env::temp_dir().join(format!("maestro-artifact-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let zip = |file: &str, entries: &[(&str, String)]| {
+            let mut writer = zip::ZipWriter::new(fs::File::create(dir.join(file)).unwrap());
+            for (name, content) in entries {
+                writer.start_file(*name, zip::write::FileOptions::default()).unwrap();
+                writer.write_all(content.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        };
+        let manifest = |version: &str| json!({ "name": "acme/zipped", "version": version, "require": { "php": ">=8.1" } }).to_string();
+        zip("zipped-1.0.0.zip", &[("composer.json", manifest("1.0.0")), ("src/Lib.php", "<?php".into())]);
+        // github-style archives keep everything one directory down
+        zip("zipped-1.1.0.zip", &[("acme-zipped-1a2b3c/composer.json", manifest("1.1.0")), ("acme-zipped-1a2b3c/vendor/x/composer.json", manifest("9.0.0"))]);
+        zip("readme.zip", &[("README.md", "no manifest".into())]);
+        fs::write(dir.join("notes.txt"), "not an archive").unwrap();
+
+        let set = repositories(json!([{ "type": "artifact", "url": dir.to_string_lossy() }, { "packagist.org": false }])).await;
+        let found = set.find("acme/zipped", false).await.unwrap();
+        let listed: Vec<&str> = found.iter().map(|v| v.version.as_str()).collect();
+        assert_eq!(listed, ["1.0.0", "1.1.0"]);
+        assert_eq!(found[0].require.get("php").map(String::as_str), Some(">=8.1"));
+        let dist = found[1].dist.as_ref().unwrap();
+        assert_eq!(dist.r#type, "zip");
+        assert_eq!(Path::new(&dist.url), fs::canonicalize(&dir).unwrap().join("zipped-1.1.0.zip"));
+
+        assert!(load_artifact(&dir.join("missing").to_string_lossy()).is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,38 +1,685 @@
-use semver::{Version, VersionReq};
+use std::cmp::Ordering;
+use anyhow::{bail, Context, Result};
+use crate::version::{normalize, parse_stability, PhpVersion, Stability};
 
-pub fn to_rust_version(v: &str) -> Option<Version> {
-    // convert v1.2.3 -> 1.2.3
-   let clean = v.trim_start_matches('v');
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// Parsed form of a composer version constraint.
+// Every composer shorthand (^, ~, wildcards, hyphen ranges) is lowered to plain comparisons,
+// so matching only has to deal with these four shapes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    Any,
+    Compare(Operator, PhpVersion),
+    And(Vec<Constraint>),
+    Or(Vec<Constraint>),
+}
+
+impl Constraint {
+    // "^1.0 || >=2.0 <2.5, !=2.3.1" -> Or([And(..), And(..)])
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut input = input.trim();
+        if input.is_empty() {
+            bail!("Empty version constraint");
+        }
+
+        // "dev-main as 1.0.x-dev" aliases a branch; only the real version is matched
+        if let Some((real, _alias)) = input.split_once(" as ") {
+            input = real.trim();
+        }
+
+        // composer accepts both "|" and "||" as OR
+        let mut alternatives = Vec::new();
+        for part in input.replace("||", "|").split('|') {
+            let part = part.trim();
+            if part.is_empty() {
+                bail!("Invalid version constraint \"{}\": empty OR branch", input);
+            }
+            alternatives.push(parse_and(part).with_context(|| format!("Invalid version constraint \"{}\"", input))?);
+        }
+
+        Ok(simplify(alternatives, Constraint::Or))
+    }
+
+    pub fn matches(&self, v: &PhpVersion) -> bool {
+        match self {
+            Constraint::Any => true,
+            Constraint::Compare(op, bound) => compare(*op, v, bound),
+            Constraint::And(all) => all.iter().all(|c| c.matches(v)),
+            Constraint::Or(any) => any.iter().any(|c| c.matches(v)),
+        }
+    }
+}
+
+impl Constraint {
+    // Whether some version could satisfy both constraints, e.g. a "provide" of "1.0|2.0" against a
+    // requirement of "^2.0". Both sides are flattened into a union of numeric intervals plus the
+    // named branches they accept.
+    pub fn intersects(&self, other: &Constraint) -> bool {
+        let (ours, our_branches) = self.extent();
+        let (theirs, their_branches) = other.extent();
+
+        ours.iter().any(|a| theirs.iter().any(|b| a.intersect(b).is_some()))
+            || our_branches.overlaps(&their_branches)
+    }
+
+    fn extent(&self) -> (Vec<Interval>, Branches) {
+        match self {
+            Constraint::Any => (vec![Interval::ANY], Branches::All),
+            Constraint::Compare(op, v) if v.is_branch() => match op {
+                Operator::Eq => (vec![], Branches::Only(vec![v.normalized().to_string()])),
+                Operator::Ne => (vec![Interval::ANY], Branches::All),
+                _ => (vec![], Branches::Only(vec![])),
+            },
+            Constraint::Compare(op, v) => {
+                let point = || Bound::Inclusive(v.clone());
+                let open = || Bound::Exclusive(v.clone());
+                let interval = |low, high| vec![Interval { low, high }];
+                match op {
+                    Operator::Eq => (interval(point(), point()), Branches::Only(vec![])),
+                    Operator::Ne => (
+                        vec![
+                            Interval { low: Bound::Unbounded, high: open() },
+                            Interval { low: open(), high: Bound::Unbounded },
+                        ],
+                        Branches::All,
+                    ),
+                    Operator::Lt => (interval(Bound::Unbounded, open()), Branches::Only(vec![])),
+                    Operator::Le => (interval(Bound::Unbounded, point()), Branches::Only(vec![])),
+                    Operator::Gt => (interval(open(), Bound::Unbounded), Branches::Only(vec![])),
+                    Operator::Ge => (interval(point(), Bound::Unbounded), Branches::Only(vec![])),
+                }
+            }
+            Constraint::And(all) => all.iter().fold((vec![Interval::ANY], Branches::All), |(acc, branches), c| {
+                let (next, next_branches) = c.extent();
+                let intervals = acc.iter()
+                    .flat_map(|a| next.iter().filter_map(move |b| a.intersect(b)))
+                    .collect();
+                (intervals, branches.intersect(next_branches))
+            }),
+            Constraint::Or(any) => any.iter().fold((vec![], Branches::Only(vec![])), |(mut acc, branches), c| {
+                let (next, next_branches) = c.extent();
+                acc.extend(next);
+                (acc, branches.union(next_branches))
+            }),
+        }
+    }
+}
+
+// the named branches ("dev-main") a constraint accepts
+#[derive(Debug, Clone)]
+enum Branches {
+    All,
+    Only(Vec<String>),
+}
+
+impl Branches {
+    fn intersect(self, other: Branches) -> Branches {
+        match (self, other) {
+            (Branches::All, other) | (other, Branches::All) => other,
+            (Branches::Only(a), Branches::Only(b)) => Branches::Only(a.into_iter().filter(|x| b.contains(x)).collect()),
+        }
+    }
+
+    fn union(self, other: Branches) -> Branches {
+        match (self, other) {
+            (Branches::All, _) | (_, Branches::All) => Branches::All,
+            (Branches::Only(mut a), Branches::Only(b)) => {
+                a.extend(b);
+                Branches::Only(a)
+            }
+        }
+    }
+
+    fn overlaps(&self, other: &Branches) -> bool {
+        match (self, other) {
+            (Branches::All, Branches::All) => true,
+            (Branches::All, Branches::Only(x)) | (Branches::Only(x), Branches::All) => !x.is_empty(),
+            (Branches::Only(a), Branches::Only(b)) => a.iter().any(|x| b.contains(x)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Bound {
+    Unbounded,
+    Inclusive(PhpVersion),
+    Exclusive(PhpVersion),
+}
+
+// a contiguous range of numeric versions
+#[derive(Debug, Clone)]
+struct Interval {
+    low: Bound,
+    high: Bound,
+}
+
+impl Interval {
+    const ANY: Interval = Interval { low: Bound::Unbounded, high: Bound::Unbounded };
+
+    fn intersect(&self, other: &Interval) -> Option<Interval> {
+        let interval = Interval {
+            low: tighter(&self.low, &other.low, Ordering::Greater),
+            high: tighter(&self.high, &other.high, Ordering::Less),
+        };
+
+        let empty = match (&interval.low, &interval.high) {
+            (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+            (Bound::Inclusive(l), Bound::Inclusive(h)) => l > h,
+            (Bound::Inclusive(l) | Bound::Exclusive(l), Bound::Inclusive(h) | Bound::Exclusive(h)) => l >= h,
+        };
+        (!empty).then_some(interval)
+    }
+}
+
+// pick the more restrictive of two bounds; `prefer` is Greater for lower bounds, Less for upper bounds
+fn tighter(a: &Bound, b: &Bound, prefer: Ordering) -> Bound {
+    match (a, b) {
+        (Bound::Unbounded, other) | (other, Bound::Unbounded) => other.clone(),
+        (Bound::Inclusive(x) | Bound::Exclusive(x), Bound::Inclusive(y) | Bound::Exclusive(y)) => match x.cmp(y) {
+            Ordering::Equal if matches!(a, Bound::Exclusive(_)) => a.clone(),
+            Ordering::Equal => b.clone(),
+            ordering if ordering == prefer => a.clone(),
+            _ => b.clone(),
+        },
+    }
+}
+
+// composer's Constraint::versionCompare(): named branches ("dev-main") are not on the numeric
+// line, so they only ever match an exact == or !=
+fn compare(op: Operator, v: &PhpVersion, bound: &PhpVersion) -> bool {
+    if v.is_branch() || bound.is_branch() {
+        return match op {
+            Operator::Eq => v.normalized() == bound.normalized(),
+            Operator::Ne => v.normalized() != bound.normalized(),
+            _ => false,
+        };
+    }
+
+    match op {
+        Operator::Eq => v == bound,
+        Operator::Ne => v != bound,
+        Operator::Lt => v < bound,
+        Operator::Le => v <= bound,
+        Operator::Gt => v > bound,
+        Operator::Ge => v >= bound,
+    }
+}
+
+fn simplify(mut list: Vec<Constraint>, wrap: fn(Vec<Constraint>) -> Constraint) -> Constraint {
+    if list.len() == 1 {
+        list.remove(0)
+    } else {
+        wrap(list)
+    }
+}
+
+// AND-ed constraints are separated by spaces or commas, except around the " - " of a hyphen range
+fn parse_and(part: &str) -> Result<Constraint> {
+    let tokens: Vec<&str> = part
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let mut constraints = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+
+        // "1.0 - 2.0"
+        if i + 2 < tokens.len() && tokens.get(i + 1) == Some(&"-") {
+            constraints.push(parse_hyphen_range(token, tokens[i + 2])?);
+            i += 3;
+            continue;
+        }
+
+        // ">= 1.0": an operator separated from its version
+        if is_operator(token) {
+            let Some(version) = tokens.get(i + 1) else {
+                bail!("Operator \"{}\" is missing a version", token);
+            };
+            constraints.push(parse_atom(&format!("{}{}", token, version))?);
+            i += 2;
+            continue;
+        }
+
+        constraints.push(parse_atom(token)?);
+        i += 1;
+    }
+
+    Ok(simplify(constraints, Constraint::And))
+}
+
+// the individual constraints of an OR/AND expression: ">=1.0 <2.0 || dev-main" -> [">=1.0", "<2.0", "dev-main"]
+pub fn atoms(constraint: &str) -> Vec<String> {
+    let constraint = constraint.split_once(" as ").map(|(real, _)| real).unwrap_or(constraint);
+
+    let mut atoms = Vec::new();
+    for part in constraint.replace("||", "|").split('|') {
+        let tokens: Vec<&str> = part
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty() && *t != "-")
+            .collect();
+
+        let mut i = 0;
+        while i < tokens.len() {
+            if is_operator(tokens[i]) && i + 1 < tokens.len() {
+                atoms.push(format!("{}{}", tokens[i], tokens[i + 1]));
+                i += 2;
+            } else {
+                atoms.push(tokens[i].to_string());
+                i += 1;
+            }
+        }
+    }
+    atoms
+}
+
+fn is_operator(token: &str) -> bool {
+    matches!(token, ">=" | "<=" | ">" | "<" | "=" | "==" | "!=" | "<>")
+}
+
+fn parse_atom(atom: &str) -> Result<Constraint> {
+    // a "#ref" suffix pins a commit for dev versions; it plays no part in matching
+    let atom = match atom.split_once('#') {
+        Some((version, _reference)) if version.starts_with("dev-") || version.ends_with("-dev") => version,
+        _ => atom,
+    };
+
+    let (atom, flag) = split_stability_flag(atom)?;
+
+    if atom.is_empty() || matches!(atom, "*" | "x" | "X" | "*.*" | "*.*.*" | "v*") {
+        return Ok(Constraint::Any);
+    }
+
+    if let Some(rest) = atom.strip_prefix('^') {
+        return parse_caret(rest);
+    }
+
+    if let Some(rest) = atom.strip_prefix('~') {
+        if rest.starts_with('>') {
+            bail!("\"~>\" is not a valid composer operator, use \"~\" instead");
+        }
+        return parse_tilde(rest);
+    }
+
+    if atom.ends_with(".*") || atom.ends_with(".x") || atom.ends_with(".X") {
+        return parse_wildcard(atom);
+    }
+
+    let mut op = Operator::Eq;
+    let mut version = atom;
+    for (prefix, prefix_op) in [
+        (">=", Operator::Ge),
+        ("<=", Operator::Le),
+        ("<>", Operator::Ne),
+        ("!=", Operator::Ne),
+        ("==", Operator::Eq),
+        (">", Operator::Gt),
+        ("<", Operator::Lt),
+        ("=", Operator::Eq),
+    ] {
+        if let Some(rest) = atom.strip_prefix(prefix) {
+            op = prefix_op;
+            version = rest;
+            break;
+        }
+    }
+
+    let mut normalized = match normalize(version) {
+        Ok(normalized) => normalized,
+        // "foo-dev" is how some people spell the branch "dev-foo"
+        Err(_) if op == Operator::Eq && version.ends_with("-dev") && !version.starts_with(|c: char| c.is_ascii_digit()) => {
+            normalize(&format!("dev-{}", &version[..version.len() - 4]))?
+        }
+        Err(e) => return Err(e),
+    };
 
-    let parts: Vec<&str> = clean.split('.').collect();
-    let standardized = if parts.len() > 3 {
-        format!("{}.{}.{}", parts[0], parts[1], parts[2])
+    // ">=1.0@beta" lets betas of 1.0 through; a plain ">=1.0" or "<2.0" includes/excludes 1.0 and 2.0 pre-releases
+    match flag {
+        Some(flag) if op != Operator::Eq && flag != Stability::Stable && parse_stability(&normalized) == Stability::Stable => {
+            normalized.push('-');
+            normalized.push_str(flag.as_str());
+        }
+        _ if matches!(op, Operator::Lt | Operator::Ge)
+            && !version.starts_with("dev-")
+            && parse_stability(&normalized) == Stability::Stable
+            && !normalized.contains("-patch") =>
+        {
+            normalized.push_str("-dev");
+        }
+        _ => {}
+    }
+
+    Ok(Constraint::Compare(op, PhpVersion::from_normalized(&normalized)))
+}
+
+// "^1.0@beta" -> ("^1.0", Some(Beta))
+pub fn split_stability_flag(atom: &str) -> Result<(&str, Option<Stability>)> {
+    match atom.rsplit_once('@') {
+        Some((rest, flag)) => match Stability::parse(flag) {
+            Some(stability) => Ok((rest, Some(stability))),
+            None => bail!("Unknown stability flag \"@{}\"", flag),
+        },
+        None => Ok((atom, None)),
+    }
+}
+
+// ^1.2.3 -> >=1.2.3 <2.0.0, ^0.3 -> >=0.3.0 <0.4.0, ^0.0.3 -> >=0.0.3 <0.0.4
+fn parse_caret(rest: &str) -> Result<Constraint> {
+    let partial = parse_partial(rest)?;
+    let position = if partial.parts[0] != 0 || partial.given == 1 {
+        1
+    } else if partial.parts[1] != 0 || partial.given == 2 {
+        2
     } else {
-        clean.to_string()
+        3
     };
 
-    Version::parse(&standardized).ok()
+    Ok(range(partial.lower_bound(rest)?, partial.bump(position)))
 }
 
-pub fn version_matches(req_str: &str, version_str: &str) -> bool {
-    let v = match to_rust_version(version_str) {
-        Some(found) => found,
-        None => return false
+// ~1.2 -> >=1.2.0 <2.0.0, ~1.2.3 -> >=1.2.3 <1.3.0
+fn parse_tilde(rest: &str) -> Result<Constraint> {
+    let partial = parse_partial(rest)?;
+    let position = partial.given.saturating_sub(1).max(1);
+
+    Ok(range(partial.lower_bound(rest)?, partial.bump(position)))
+}
+
+// 1.2.* -> >=1.2.0 <1.3.0
+fn parse_wildcard(atom: &str) -> Result<Constraint> {
+    let prefix = atom.trim_end_matches(['*', 'x', 'X', '.']);
+    let partial = parse_partial(prefix)?;
+    if partial.has_modifier {
+        bail!("Wildcard constraint \"{}\" cannot carry a stability suffix", atom);
+    }
+
+    let upper = Constraint::Compare(Operator::Lt, partial.bump(partial.given));
+    if partial.parts == [0; 4] {
+        return Ok(upper);
+    }
+
+    let lower = PhpVersion::from_normalized(&format!("{}-dev", partial.padded()));
+    Ok(Constraint::And(vec![Constraint::Compare(Operator::Ge, lower), upper]))
+}
+
+// 1.0 - 2.0 -> >=1.0.0 <2.1.0, 1.0 - 2.0.1 -> >=1.0.0 <=2.0.1
+fn parse_hyphen_range(low: &str, high: &str) -> Result<Constraint> {
+    let lower = parse_partial(low)?.lower_bound(low)?;
+    let partial_high = parse_partial(high)?;
+
+    let upper = if partial_high.given >= 3 || partial_high.has_modifier {
+        Constraint::Compare(Operator::Le, PhpVersion::from_normalized(&normalize(high)?))
+    } else {
+        let position = if partial_high.given == 1 { 1 } else { 2 };
+        Constraint::Compare(Operator::Lt, partial_high.bump(position))
     };
 
-    // PHP uses "||" for OR
-    // semver doesn't natively support "||" in a single string
-    // split by "||" and check if any part matches
-    for part in req_str.split("||") {
-        let clean_part = part.trim();
+    Ok(Constraint::And(vec![Constraint::Compare(Operator::Ge, lower), upper]))
+}
 
-        // valid, check if it matches
-        if let Ok(req) = VersionReq::parse(clean_part) {
-            if req.matches(&v) {
-                return true;
-            }
+fn range(low: PhpVersion, high: PhpVersion) -> Constraint {
+    Constraint::And(vec![
+        Constraint::Compare(Operator::Ge, low),
+        Constraint::Compare(Operator::Lt, high),
+    ])
+}
+
+// the numeric part of a version as written in a constraint, which may leave out trailing segments ("1.2")
+struct PartialVersion {
+    parts: [u64; 4],
+    given: usize,
+    has_modifier: bool,
+}
+
+impl PartialVersion {
+    fn padded(&self) -> String {
+        self.parts.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(".")
+    }
+
+    // without an explicit stability the lower bound also admits pre-releases of that version
+    fn lower_bound(&self, written: &str) -> Result<PhpVersion> {
+        let mut normalized = normalize(written)?;
+        if !self.has_modifier {
+            normalized.push_str("-dev");
+        }
+        Ok(PhpVersion::from_normalized(&normalized))
+    }
+
+    // increment the segment at `position` (1-based), zero everything after it, and exclude its pre-releases
+    fn bump(&self, position: usize) -> PhpVersion {
+        let mut parts = self.parts;
+        let index = position.clamp(1, 4) - 1;
+        parts[index] += 1;
+        for part in parts.iter_mut().skip(index + 1) {
+            *part = 0;
+        }
+        let bumped = PartialVersion { parts, given: 4, has_modifier: false };
+        PhpVersion::from_normalized(&format!("{}-dev", bumped.padded()))
+    }
+}
+
+fn parse_partial(input: &str) -> Result<PartialVersion> {
+    let clean = input.trim().trim_start_matches(['v', 'V']);
+
+    let split_at = clean
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(clean.len());
+    let (numbers, modifier) = clean.split_at(split_at);
+
+    let mut parts = [0u64; 4];
+    let mut given = 0;
+    for (i, segment) in numbers.split('.').enumerate() {
+        if i >= 4 || segment.is_empty() {
+            bail!("Invalid version string \"{}\"", input);
+        }
+        parts[i] = segment.parse().with_context(|| format!("Invalid version string \"{}\"", input))?;
+        given += 1;
+    }
+
+    // validates the modifier the same way a full version would be
+    normalize(input)?;
+
+    Ok(PartialVersion { parts, given, has_modifier: !modifier.is_empty() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(constraint: &str, version: &str) -> bool {
+        Constraint::parse(constraint).unwrap().matches(&PhpVersion::from_normalized(version))
+    }
+
+    fn bound(normalized: &str) -> PhpVersion {
+        PhpVersion::from_normalized(normalized)
+    }
+
+    // (constraint, version, expected) following composer's VersionParser semantics
+    const CASES: &[(&str, &str, bool)] = &[
+        // exact versions
+        ("1.2.3", "1.2.3.0", true),
+        ("1.2.3", "1.2.4.0", false),
+        ("1.2", "1.2.0.0", true),
+        ("1.2", "1.2.1.0", false),
+        ("v1.2.3", "1.2.3.0", true),
+        ("=1.2.3", "1.2.3.0", true),
+        ("==1.2.3", "1.2.3.0", true),
+        // comparison operators
+        (">=1.0", "1.0.0.0", true),
+        (">=1.0", "0.9.9.0", false),
+        (">1.0", "1.0.0.0", false),
+        (">1.0", "1.0.1.0", true),
+        ("<2.0", "1.9.9.0", true),
+        ("<2.0", "2.0.0.0", false),
+        ("<=2.0", "2.0.0.0", true),
+        ("!=1.5.0", "1.5.0.0", false),
+        ("!=1.5.0", "1.5.1.0", true),
+        ("<>1.5.0", "1.5.0.0", false),
+        (">= 1.0", "1.3.0.0", true),
+        // AND with spaces and commas
+        (">=1.0 <2.0", "1.5.0.0", true),
+        (">=1.0 <2.0", "2.0.0.0", false),
+        (">=1.0,<2.0", "1.5.0.0", true),
+        (">=1.0, <2.0", "0.5.0.0", false),
+        (">=1.0 <2.0 !=1.5.0", "1.5.0.0", false),
+        // OR with single and double pipes
+        ("^1.0 || ^2.0", "2.3.0.0", true),
+        ("^1.0 | ^2.0", "2.3.0.0", true),
+        ("^1.0|^2.0", "1.1.0.0", true),
+        ("^1.0 || ^2.0", "3.0.0.0", false),
+        (">=1.0 <1.1 || >=1.2", "1.0.5.0", true),
+        (">=1.0 <1.1 || >=1.2", "1.1.5.0", false),
+        (">=1.0 <1.1 || >=1.2", "1.3.0.0", true),
+        // hyphen ranges: partial upper bound is a wildcard, full upper bound is inclusive
+        ("1.0 - 2.0", "1.0.0.0", true),
+        ("1.0 - 2.0", "2.0.9.0", true),
+        ("1.0 - 2.0", "2.1.0.0", false),
+        ("1.0.0 - 2.1.0", "2.1.0.0", true),
+        ("1.0.0 - 2.1.0", "2.1.1.0", false),
+        ("1 - 2", "2.9.0.0", true),
+        ("1 - 2", "3.0.0.0", false),
+        ("1.0 - 2.0", "0.9.0.0", false),
+        // wildcards
+        ("*", "0.0.1.0", true),
+        ("1.2.*", "1.2.0.0", true),
+        ("1.2.*", "1.2.99.0", true),
+        ("1.2.*", "1.3.0.0", false),
+        ("1.*", "1.99.0.0", true),
+        ("1.*", "2.0.0.0", false),
+        ("1.x", "1.5.0.0", true),
+        ("1.2.x", "1.3.0.0", false),
+        // tilde: the last given segment may move
+        ("~1", "1.9.0.0", true),
+        ("~1", "2.0.0.0", false),
+        ("~1.2", "1.2.0.0", true),
+        ("~1.2", "1.9.0.0", true),
+        ("~1.2", "2.0.0.0", false),
+        ("~1.2", "1.1.0.0", false),
+        ("~1.2.3", "1.2.9.0", true),
+        ("~1.2.3", "1.3.0.0", false),
+        ("~1.2.3", "1.2.2.0", false),
+        // caret: the leftmost non-zero segment is locked
+        ("^1.2.3", "1.2.3.0", true),
+        ("^1.2.3", "1.9.0.0", true),
+        ("^1.2.3", "2.0.0.0", false),
+        ("^1.2.3", "1.2.2.0", false),
+        ("^1", "1.9.0.0", true),
+        ("^0.3", "0.3.9.0", true),
+        ("^0.3", "0.4.0.0", false),
+        ("^0.3.2", "0.3.1.0", false),
+        ("^0.0.3", "0.0.3.0", true),
+        ("^0.0.3", "0.0.4.0", false),
+        ("^0", "0.9.0.0", true),
+        ("^0", "1.0.0.0", false),
+        ("^0.0", "0.0.9.0", true),
+        ("^0.0", "0.1.0.0", false),
+        // stability flags do not change which versions match
+        ("^1.0@beta", "1.5.0.0", true),
+        ("^1.0@stable", "2.0.0.0", false),
+        ("1.0.0@dev", "1.0.0.0", true),
+        ("@dev", "3.0.0.0", true),
+        // four numeric segments are significant
+        ("1.2.3.4", "1.2.3.4", true),
+        ("1.2.3.4", "1.2.3.0", false),
+        (">1.2.3.0", "1.2.3.4", true),
+        ("~1.2.3.4", "1.2.3.9", true),
+        ("~1.2.3.4", "1.2.4.0", false),
+        // pre-releases: ranges admit pre-releases of their lower bound, never of their upper bound
+        ("^1.0", "1.0.0.0-beta2", true),
+        ("^1.0", "2.0.0.0-beta1", false),
+        ("^1.0", "2.0.0.0-dev", false),
+        ("<2.0", "2.0.0.0-RC1", false),
+        ("<=2.0", "2.0.0.0-RC1", true),
+        (">=1.0", "1.0.0.0-alpha1", true),
+        (">1.0", "1.0.0.0-patch1", true),
+        (">=1.0.0-beta", "1.0.0.0-alpha2", false),
+        (">=1.0.0-beta", "1.0.0.0-beta3", true),
+        ("1.0.0-beta2", "1.0.0.0-beta2", true),
+        ("1.0.0-b2", "1.0.0.0-beta2", true),
+        (">=2.0@beta", "2.0.0.0-beta1", true),
+        (">=2.0@beta", "2.0.0.0-alpha1", false),
+        // dev branches only match themselves
+        ("dev-main", "dev-main", true),
+        ("dev-main", "dev-develop", false),
+        ("dev-main#abc123", "dev-main", true),
+        ("main-dev", "dev-main", true),
+        ("*", "dev-main", true),
+        (">=1.0", "dev-main", false),
+        ("!=dev-main", "dev-develop", true),
+        ("2.x-dev", "2.9999999.9999999.9999999-dev", true),
+        ("^2.0@dev", "2.9999999.9999999.9999999-dev", true),
+        ("^2.0", "2.1.9999999.9999999-dev", true),
+        ("dev-main as 1.0.x-dev", "dev-main", true),
+    ];
+
+    #[test]
+    fn composer_constraint_semantics() {
+        for (constraint, version, expected) in CASES {
+            assert_eq!(
+                matches(constraint, version),
+                *expected,
+                "constraint \"{}\" against {}",
+                constraint,
+                version,
+            );
         }
     }
 
-    false
-}
\ No newline at end of file
+    #[test]
+    fn parses_into_ast() {
+        assert_eq!(Constraint::parse("*").unwrap(), Constraint::Any);
+        assert_eq!(
+            Constraint::parse("~1.2").unwrap(),
+            Constraint::And(vec![
+                Constraint::Compare(Operator::Ge, bound("1.2.0.0-dev")),
+                Constraint::Compare(Operator::Lt, bound("2.0.0.0-dev")),
+            ]),
+        );
+        assert_eq!(
+            Constraint::parse("!=1.0 | 2.0").unwrap(),
+            Constraint::Or(vec![
+                Constraint::Compare(Operator::Ne, bound("1.0.0.0")),
+                Constraint::Compare(Operator::Eq, bound("2.0.0.0")),
+            ]),
+        );
+    }
+
+    #[test]
+    fn intersects_constraints() {
+        let cases = [
+            ("1.0|2.0|3.0", "^2.0", true),
+            ("1.0.0", "^2.0", false),
+            ("*", "^1.0", true),
+            (">=1.0 <2.0", ">=2.0", false),
+            (">=1.0 <=2.0", ">=2.0", true),
+            (">1.0 <3.0", ">1.0 <3.0", true),
+            ("!=1.0.0", "1.0.0", false),
+            ("!=1.0.0", "^1.0", true),
+            ("dev-main", "dev-main", true),
+            ("dev-main", "^1.0", false),
+            ("dev-main", "*", true),
+        ];
+        for (a, b, expected) in cases {
+            let (a, b) = (Constraint::parse(a).unwrap(), Constraint::parse(b).unwrap());
+            assert_eq!(a.intersects(&b), expected, "{:?} against {:?}", a, b);
+            assert_eq!(b.intersects(&a), expected, "{:?} against {:?}", b, a);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_constraints() {
+        for invalid in ["", "^1.0 ||", "~>1.0", "^1.0@unstable", "foo", "1.2.3.4.5", ">="] {
+            assert!(Constraint::parse(invalid).is_err(), "\"{}\" should not parse", invalid);
+        }
+    }
+}
@@ -0,0 +1,1264 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::cmp::Reverse;
+use indexmap::IndexMap;
+use anyhow::Result;
+use crate::manifest::ComposerManifest;
+use crate::platform::Platform;
+use crate::registry::PackageVersion;
+use crate::repository::matches_name;
+use crate::semver_compat::{atoms, split_stability_flag, Constraint, Operator};
+use crate::version::{parse_stability, PhpVersion, Stability};
+
+// platform requirements (php itself, extensions, system libs) are not packages on packagist
+pub fn is_platform_package(name: &str) -> bool {
+    name == "php"
+        || name.starts_with("php-")
+        || name == "hhvm"
+        || name.starts_with("ext-")
+        || name.starts_with("lib-")
+        || name == "composer"
+        || name == "composer-plugin-api"
+        || name == "composer-runtime-api"
+}
+
+// a dependency on another package, with its constraint parsed once up front
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub target: String,
+    pub pretty_constraint: String,
+    pub constraint: Constraint,
+}
+
+impl Link {
+    pub fn parse(target: &str, constraint: &str) -> Result<Self> {
+        Ok(Self {
+            target: target.to_string(),
+            pretty_constraint: constraint.to_string(),
+            constraint: Constraint::parse(constraint)?,
+        })
+    }
+
+    // metadata from the registry may contain constraints we can't parse; those match nothing
+    fn parse_lenient(target: &str, constraint: &str) -> Self {
+        Self::parse(target, constraint).unwrap_or_else(|_| Self {
+            target: target.to_string(),
+            pretty_constraint: constraint.to_string(),
+            constraint: Constraint::Or(vec![]),
+        })
+    }
+
+    // "self.version" stands for the version of the package declaring the link;
+    // the root package has no version, so there it matches anything
+    fn parse_with_self(target: &str, constraint: &str, own_version: Option<&PhpVersion>) -> Self {
+        if constraint.trim() != "self.version" {
+            return Self::parse_lenient(target, constraint);
+        }
+        Self {
+            target: target.to_string(),
+            pretty_constraint: constraint.to_string(),
+            constraint: match own_version {
+                Some(version) => Constraint::Compare(Operator::Eq, version.clone()),
+                None => Constraint::Any,
+            },
+        }
+    }
+}
+
+fn parse_links(links: &BTreeMap<String, String>, own_version: Option<&PhpVersion>) -> Vec<Link> {
+    let mut parsed: Vec<Link> = links.iter()
+        .map(|(target, constraint)| Link::parse_with_self(target, constraint, own_version))
+        .collect();
+    parsed.sort_by(|a, b| a.target.cmp(&b.target));
+    parsed
+}
+
+// a single version of a package as seen by the solver
+pub struct Candidate {
+    pub name: String,
+    pub package: PackageVersion,
+    pub version: PhpVersion,
+    pub requires: Vec<Link>,
+    pub conflicts: Vec<Link>,
+    pub replaces: Vec<Link>,
+    pub provides: Vec<Link>,
+}
+
+impl Candidate {
+    fn new(name: &str, package: PackageVersion, version: PhpVersion) -> Self {
+        Self {
+            name: name.to_string(),
+            requires: parse_links(&package.require, Some(&version)),
+            conflicts: parse_links(&package.conflict, Some(&version)),
+            replaces: parse_links(&package.replace, Some(&version)),
+            provides: parse_links(&package.provide, Some(&version)),
+            package,
+            version,
+        }
+    }
+
+    // installing this version fulfils `link` by being, replacing or providing its target
+    fn satisfies(&self, link: &Link) -> bool {
+        if self.name == link.target {
+            return link.constraint.matches(&self.version);
+        }
+        self.replaces.iter().chain(&self.provides)
+            .any(|l| l.target == link.target && l.constraint.intersects(&link.constraint))
+    }
+
+    // names this version takes up: its own, plus everything it replaces
+    fn occupies(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.replaces.iter().map(|l| l.target.as_str()))
+    }
+
+    fn pretty(&self) -> String {
+        format!("{} {}", self.name, self.package.version)
+    }
+}
+
+// which versions may be installed at all (minimum-stability, per-package @flags)
+// and whether stable releases win over newer unstable ones (prefer-stable)
+#[derive(Debug, Clone)]
+pub struct StabilityPolicy {
+    pub minimum: Stability,
+    pub flags: BTreeMap<String, Stability>,
+    pub prefer_stable: bool,
+}
+
+impl Default for StabilityPolicy {
+    fn default() -> Self {
+        Self { minimum: Stability::Stable, flags: BTreeMap::new(), prefer_stable: false }
+    }
+}
+
+impl StabilityPolicy {
+    pub fn accepts(&self, name: &str, version: &PhpVersion) -> bool {
+        let minimum = self.flags.get(name).copied().unwrap_or(self.minimum);
+        version.stability() >= minimum
+    }
+
+    // whether branches (dev-main, 2.x-dev) of `name` are worth loading for this constraint:
+    // either dev versions are allowed, or the constraint asks for one and we want to say why it can't have it
+    pub fn wants_dev(&self, name: &str, constraint: &str) -> bool {
+        if self.flags.get(name).copied().unwrap_or(self.minimum) == Stability::Dev {
+            return true;
+        }
+        atoms(constraint).iter().any(|atom| match split_stability_flag(atom) {
+            Ok((version, flag)) => flag == Some(Stability::Dev)
+                || parse_stability(version.trim_start_matches(['=', '>', '<', '!', '^', '~'])) == Stability::Dev,
+            Err(_) => false,
+        })
+    }
+}
+
+// every known version of every package that may take part in resolution
+#[derive(Default)]
+pub struct Pool {
+    packages: HashMap<String, Vec<Candidate>>,
+    // name -> packages that replace or provide it in at least one version
+    providers: HashMap<String, Vec<String>>,
+    // versions dropped for being less stable than allowed, kept to explain failures
+    unstable: HashMap<String, Vec<(String, PhpVersion)>>,
+    // packages a partial update keeps at their locked version
+    pinned: HashSet<String>,
+    policy: StabilityPolicy,
+    platform: Platform,
+}
+
+impl Pool {
+    pub fn new(policy: StabilityPolicy, platform: Platform) -> Self {
+        let mut pool = Self { policy, ..Default::default() };
+        // platform packages are whatever is installed, whatever its stability
+        for (name, package) in platform.to_packages() {
+            let version = PhpVersion::from_normalized(&package.version_normalized);
+            pool.packages.insert(name.clone(), vec![Candidate::new(&name, package, version)]);
+        }
+        pool.platform = platform;
+        pool
+    }
+
+    // the locked version becomes the only candidate, whatever its stability; nothing else is added later
+    pub fn pin(&mut self, package: PackageVersion) {
+        let Some(name) = package.name.clone() else { return; };
+        let version = PhpVersion::from_normalized(&package.version_normalized);
+        self.packages.remove(&name);
+        self.add(&name, vec![Candidate::new(&name, package, version)]);
+        self.pinned.insert(name);
+    }
+
+    pub fn is_pinned(&self, name: &str) -> bool {
+        self.pinned.contains(name)
+    }
+
+    // add versions of `name`; tagged releases and branches arrive separately
+    pub fn insert(&mut self, name: &str, versions: Vec<PackageVersion>) {
+        if self.is_pinned(name) {
+            return;
+        }
+        let mut candidates: Vec<Candidate> = self.packages.remove(name).unwrap_or_default();
+        candidates.extend(versions.into_iter()
+            .filter_map(|mut package| {
+                let version = if package.version_normalized.is_empty() {
+                    PhpVersion::parse(&package.version).ok()?
+                } else {
+                    PhpVersion::from_normalized(&package.version_normalized)
+                };
+                if !self.policy.accepts(name, &version) {
+                    self.unstable.entry(name.to_string()).or_default().push((package.version.clone(), version));
+                    return None;
+                }
+                if package.name.is_none() {
+                    package.name = Some(name.to_string());
+                }
+                Some(Candidate::new(name, package, version))
+            }));
+        self.add(name, candidates);
+    }
+
+    fn add(&mut self, name: &str, mut candidates: Vec<Candidate>) {
+        // newest first, so the solver tries the most recent release before older ones;
+        // with prefer-stable the most stable releases are tried before any pre-release
+        if self.policy.prefer_stable {
+            candidates.sort_by_key(|c| Reverse((c.version.stability(), c.version.clone())));
+        } else {
+            candidates.sort_by(|a, b| b.version.cmp(&a.version));
+        }
+
+        for candidate in &candidates {
+            for link in candidate.replaces.iter().chain(&candidate.provides) {
+                let providers = self.providers.entry(link.target.clone()).or_default();
+                if !providers.iter().any(|p| p == name) {
+                    providers.push(name.to_string());
+                }
+            }
+        }
+        self.packages.insert(name.to_string(), candidates);
+    }
+
+    // platform requirements the platform can't tell us about are assumed to be met
+    fn ignores(&self, name: &str) -> bool {
+        is_platform_package(name) && !self.platform.checks(name)
+    }
+
+    pub fn versions(&self, name: &str) -> &[Candidate] {
+        self.packages.get(name).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn matching<'a>(&'a self, name: &str, constraint: &'a Constraint) -> impl Iterator<Item = &'a Candidate> + 'a {
+        self.versions(name).iter()
+            .filter(move |c| constraint.matches(&c.version))
+    }
+
+    // every version that could fulfil a requirement on `name`:
+    // the package's own versions first, then those of packages replacing or providing it
+    fn what_provides(&self, name: &str) -> Vec<&Candidate> {
+        let mut found: Vec<&Candidate> = self.versions(name).iter().collect();
+        for provider in self.providers.get(name).into_iter().flatten().filter(|p| *p != name) {
+            found.extend(self.versions(provider).iter()
+                .filter(|c| c.replaces.iter().chain(&c.provides).any(|l| l.target == name)));
+        }
+        found
+    }
+}
+
+// the root package's links, as handed to the solver
+#[derive(Debug, Default)]
+pub struct Request {
+    pub require: Vec<(String, String)>,
+    pub require_dev: Vec<(String, String)>,
+    pub conflict: Vec<(String, String)>,
+    pub replace: Vec<(String, String)>,
+    pub provide: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn from_manifest(manifest: &ComposerManifest) -> Self {
+        Self {
+            require: package_links(&manifest.require),
+            require_dev: package_links(&manifest.require_dev),
+            conflict: package_links(&manifest.conflict),
+            replace: package_links(&manifest.replace),
+            provide: package_links(&manifest.provide),
+        }
+    }
+
+    // everything the root requires, production first
+    pub fn roots(&self) -> Vec<(String, String)> {
+        self.require.iter().chain(&self.require_dev).cloned().collect()
+    }
+}
+
+fn package_links(links: &IndexMap<String, String>) -> Vec<(String, String)> {
+    let mut links: Vec<(String, String)> = links.iter()
+        .map(|(name, constraint)| (name.clone(), constraint.clone()))
+        .collect();
+    links.sort();
+    links
+}
+
+// where a requirement comes from
+#[derive(Debug, Clone)]
+pub enum Origin {
+    Root,
+    RootDev,
+    Package(String, String),
+}
+
+// a single constraint placed on a package, and who placed it
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    pub link: Link,
+    pub required_by: Origin,
+}
+
+impl Origin {
+    fn package(&self) -> Option<&str> {
+        match self {
+            Origin::Package(name, _) => Some(name),
+            Origin::Root | Origin::RootDev => None,
+        }
+    }
+}
+
+impl Requirement {
+    fn describe(&self) -> String {
+        let target = &self.link.target;
+        let constraint = &self.link.pretty_constraint;
+        match &self.required_by {
+            Origin::Package(parent, version) => format!("{} {} requires {} {}", parent, version, target, constraint),
+            Origin::Root => format!("Root composer.json requires {} {}", target, constraint),
+            Origin::RootDev => format!("Root composer.json requires {} {} (dev)", target, constraint),
+        }
+    }
+}
+
+// Split a solution into (packages, packages-dev) the way composer's lock file does:
+// a package is dev-only when nothing reachable from the root "require" leads to it.
+pub fn partition_dev(packages: Vec<PackageVersion>, require: &[(String, String)]) -> (Vec<PackageVersion>, Vec<PackageVersion>) {
+    let starts = require.iter().map(|(name, _)| name.as_str()).collect();
+    let reachable = dependency_closure(&packages, starts, |_| false);
+    packages.into_iter()
+        .partition(|p| p.name.as_deref().is_some_and(|name| reachable.contains(name)))
+}
+
+// The packages fulfilling the `required` names, directly or through replace/provide, and everything
+// they require in turn, by name. Platform requirements, and names `skip` accepts, are not followed.
+fn dependency_closure<'a>(packages: &'a [PackageVersion], required: Vec<&'a str>, skip: impl Fn(&str) -> bool) -> HashSet<String> {
+    // a requirement on a replaced or provided name leads to the package standing in for it
+    let mut by_name: HashMap<&str, Vec<&PackageVersion>> = HashMap::new();
+    for pkg in packages {
+        let Some(name) = pkg.name.as_deref() else { continue; };
+        let names = std::iter::once(name)
+            .chain(pkg.replace.keys().map(|k| k.as_str()))
+            .chain(pkg.provide.keys().map(|k| k.as_str()));
+        for provided in names {
+            by_name.entry(provided).or_default().push(pkg);
+        }
+    }
+
+    let mut reached: HashSet<String> = HashSet::new();
+    let mut stack: Vec<&str> = required.into_iter().filter(|name| !is_platform_package(name) && !skip(name)).collect();
+    while let Some(name) = stack.pop() {
+        for pkg in by_name.get(name).into_iter().flatten() {
+            let Some(pkg_name) = pkg.name.as_deref() else { continue; };
+            if !reached.insert(pkg_name.to_string()) {
+                continue;
+            }
+            stack.extend(pkg.require.keys().map(|dep| dep.as_str()).filter(|dep| !is_platform_package(dep) && !skip(dep)));
+        }
+    }
+    reached
+}
+
+// how far `update <packages>` reaches beyond the listed packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateReach {
+    Listed,
+    // -w: their dependencies too, except those the root requires itself
+    Dependencies,
+    // -W: all their dependencies
+    AllDependencies,
+}
+
+// The locked packages a partial update may change: those matching `patterns` (which may contain
+// wildcards) and, depending on `reach`, everything they depend on. Everything else stays locked.
+pub fn allowed_updates(locked: &[PackageVersion], patterns: &[String], roots: &[(String, String)], reach: UpdateReach) -> HashSet<String> {
+    let mut allowed: HashSet<String> = locked.iter()
+        .filter_map(|pkg| pkg.name.as_deref())
+        .filter(|name| patterns.iter().any(|pattern| matches_name(pattern, name)))
+        .map(String::from)
+        .collect();
+    if reach == UpdateReach::Listed {
+        return allowed;
+    }
+
+    let root_names: HashSet<&str> = roots.iter().map(|(name, _)| name.as_str()).collect();
+    let requires = locked.iter()
+        .filter(|pkg| pkg.name.as_deref().is_some_and(|name| allowed.contains(name)))
+        .flat_map(|pkg| pkg.require.keys().map(String::as_str))
+        .collect();
+    allowed.extend(dependency_closure(locked, requires, |dep| reach == UpdateReach::Dependencies && root_names.contains(dep)));
+    allowed
+}
+
+// "vendor/pkg[1.0.0, 1.1.0]", collapsing long lists the way composer does
+fn format_versions(name: &str, versions: &[&Candidate]) -> String {
+    let mut list: Vec<&str> = versions.iter().rev().map(|c| c.package.version.as_str()).collect();
+    if list.len() > 4 {
+        list = vec![list[0], "...", list[list.len() - 1]];
+    }
+    format!("{}[{}]", name, list.join(", "))
+}
+
+// why a version that would fulfil a requirement was not picked
+enum Rejection<'l> {
+    // one of its own requirements can no longer be met
+    Requires(&'l Link),
+    // it can't sit next to what is already decided
+    Clash(Clash),
+}
+
+// why a version can't sit next to what is already decided: (one version, several versions) wording,
+// and the decided package responsible (none when the root package is)
+struct Clash {
+    one: String,
+    many: String,
+    by: Option<String>,
+}
+
+// what deciding on a version added, so it can be taken back
+struct Decision {
+    occupied: Vec<String>,
+    provided: Vec<String>,
+    required: Vec<String>,
+}
+
+// why a name ran out of options: the decisions (by package name) that ruled them out, and whether
+// the constraints on that name alone were enough, whatever else is decided
+struct Failure {
+    blame: BTreeSet<String>,
+    intrinsic: bool,
+}
+
+// Backtracking dependency solver.
+// Required names are fulfilled one at a time (most constrained first), either by a version of that
+// package or by a version of another package that replaces or provides it. Every decision adds the
+// chosen version's requirements, and a version is only tried if it clashes with nothing decided so far
+// (one version per name, replace, conflict) and none of its requirements contradict what has been
+// decided already. When a name runs out of options, the failure names the decisions that caused it:
+// the solver jumps straight back to the latest of those, skipping decisions that had nothing to do
+// with it, and sets of constraints that fail on their own are remembered so no other branch retries them.
+pub struct Solver<'a> {
+    pool: &'a Pool,
+    requirements: BTreeMap<String, Vec<Requirement>>,
+    decisions: BTreeMap<String, &'a Candidate>,
+    // name -> package taking its place (the package itself, or one that replaces it)
+    occupied: BTreeMap<String, String>,
+    // name -> decided versions that are, replace or provide it
+    provided: HashMap<String, Vec<&'a Candidate>>,
+    // the root package's own replace/provide and conflict links
+    root_provides: Vec<Link>,
+    root_conflicts: Vec<Link>,
+    // explanation of the deepest point where a name had no acceptable option left
+    conflict: Option<(usize, Vec<String>)>,
+    // (name, open constraints) that no version can fulfil, whatever else is decided
+    unsatisfiable: HashSet<(String, Vec<String>)>,
+}
+
+impl<'a> Solver<'a> {
+    pub fn new(pool: &'a Pool, request: &Request) -> Result<Self> {
+        let mut requirements: BTreeMap<String, Vec<Requirement>> = BTreeMap::new();
+        let roots = request.require.iter().map(|r| (r, Origin::Root))
+            .chain(request.require_dev.iter().map(|r| (r, Origin::RootDev)));
+        for ((name, constraint), origin) in roots {
+            requirements.entry(name.clone()).or_default().push(Requirement {
+                link: Link::parse(name, constraint)?,
+                required_by: origin,
+            });
+        }
+
+        let occupied = request.replace.iter()
+            .map(|(name, _)| (name.clone(), "root composer.json".to_string()))
+            .collect();
+        let root_provides = request.replace.iter().chain(&request.provide)
+            .map(|(name, constraint)| Link::parse_with_self(name, constraint, None))
+            .collect();
+        let root_conflicts = request.conflict.iter()
+            .map(|(name, constraint)| Link::parse(name, constraint))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            pool,
+            requirements,
+            decisions: BTreeMap::new(),
+            occupied,
+            provided: HashMap::new(),
+            root_provides,
+            root_conflicts,
+            conflict: None,
+            unsatisfiable: HashSet::new(),
+        })
+    }
+
+    pub fn solve(mut self) -> Result<Vec<PackageVersion>> {
+        if self.search().is_ok() {
+            return Ok(self.decisions.into_values()
+                .filter(|c| !is_platform_package(&c.name))
+                .map(|c| c.package.clone())
+                .collect());
+        }
+
+        let mut message = String::from("Your requirements could not be resolved to an installable set of packages.\n\n  Problem 1");
+        for line in self.conflict.map(|(_, lines)| lines).unwrap_or_default() {
+            message.push_str(&format!("\n    - {}", line));
+        }
+        anyhow::bail!(message)
+    }
+
+    fn search(&mut self) -> Result<(), Failure> {
+        let Some(name) = self.next_unsatisfied() else {
+            return Ok(());
+        };
+
+        let open: Vec<Link> = self.open_links(&name).into_iter().cloned().collect();
+        let key = (name.clone(), open.iter().map(|link| format!("{:?}", link.constraint)).collect());
+        // whoever put the open requirements on `name` shares the blame for any failure here
+        let mut failure = Failure { blame: self.requirers(&name), intrinsic: true };
+        if self.unsatisfiable.contains(&key) {
+            self.record_conflict(&name);
+            return Err(failure);
+        }
+
+        for candidate in self.pool.what_provides(&name) {
+            if !open.iter().all(|link| candidate.satisfies(link)) {
+                continue;
+            }
+            if let Some(blame) = self.rejection(candidate) {
+                failure.intrinsic &= blame.is_empty();
+                failure.blame.extend(blame);
+                continue;
+            }
+
+            let decision = self.decide(candidate);
+            let Err(mut below) = self.search() else {
+                return Ok(());
+            };
+            self.undo(candidate, decision);
+
+            // this choice played no part in the failure below, so no other choice here can fix it
+            if !below.blame.remove(&candidate.name) {
+                return Err(below);
+            }
+            failure.intrinsic &= below.intrinsic && below.blame.is_empty();
+            failure.blame.extend(below.blame);
+        }
+
+        if failure.intrinsic {
+            self.unsatisfiable.insert(key);
+        }
+        self.record_conflict(&name);
+        Err(failure)
+    }
+
+    // pick the required name with the fewest remaining options
+    fn next_unsatisfied(&self) -> Option<String> {
+        self.requirements.iter()
+            .filter(|(_, reqs)| reqs.iter().any(|r| !self.is_satisfied(&r.link)))
+            .map(|(name, _)| name)
+            .min_by_key(|name| self.options(name).len())
+            .cloned()
+    }
+
+    fn is_satisfied(&self, link: &Link) -> bool {
+        self.pool.ignores(&link.target)
+            || self.root_provides.iter().any(|l| l.target == link.target && l.constraint.intersects(&link.constraint))
+            || self.provided.get(&link.target).into_iter().flatten().any(|c| c.satisfies(link))
+    }
+
+    // requirements on `name` that nothing decided so far fulfils
+    fn open_links(&self, name: &str) -> Vec<&Link> {
+        self.requirements.get(name).into_iter().flatten()
+            .map(|r| &r.link)
+            .filter(|link| !self.is_satisfied(link))
+            .collect()
+    }
+
+    // versions that fulfil every open requirement on `name` and clash with nothing decided, best first
+    fn options(&self, name: &str) -> Vec<&'a Candidate> {
+        let open = self.open_links(name);
+        self.pool.what_provides(name).into_iter()
+            .filter(|c| open.iter().all(|link| c.satisfies(link)))
+            .filter(|c| self.clash(c).is_none() && self.fits(c))
+            .collect()
+    }
+
+    // decided packages behind the requirements on `name` that are still open
+    fn requirers(&self, name: &str) -> BTreeSet<String> {
+        self.requirements.get(name).into_iter().flatten()
+            .filter(|r| !self.is_satisfied(&r.link))
+            .filter_map(|r| r.required_by.package().map(str::to_string))
+            .collect()
+    }
+
+    // Why a version can't be tried next to what has been decided: the decisions that rule it out
+    // (empty when nothing decided is to blame), or None if it is worth trying. A version is only
+    // worth trying if each of its requirements can still be met.
+    fn rejection(&self, candidate: &Candidate) -> Option<BTreeSet<String>> {
+        if let Some(blame) = self.exclusion(candidate) {
+            return Some(blame);
+        }
+        let link = self.unmet_requirement(candidate)?;
+        Some(self.pool.what_provides(&link.target).into_iter()
+            .filter(|c| c.satisfies(link))
+            .filter_map(|c| self.exclusion(c))
+            .flatten()
+            .collect())
+    }
+
+    // the decisions behind a clash or misfit, if the version has one
+    fn exclusion(&self, candidate: &Candidate) -> Option<BTreeSet<String>> {
+        if let Some(clash) = self.clash(candidate) {
+            return Some(clash.by.into_iter().collect());
+        }
+        let requirement = self.misfit(candidate)?;
+        Some(requirement.required_by.package().map(str::to_string).into_iter().collect())
+    }
+
+    fn unmet_requirement<'c>(&self, candidate: &'c Candidate) -> Option<&'c Link> {
+        candidate.requires.iter()
+            .filter(|link| !candidate.satisfies(link))
+            .find(|link| !self.link_satisfiable(link))
+    }
+
+    fn link_satisfiable(&self, link: &Link) -> bool {
+        self.is_satisfied(link)
+            || self.pool.what_provides(&link.target).into_iter()
+                .any(|c| c.satisfies(link) && self.clash(c).is_none() && self.fits(c))
+    }
+
+    // nothing else can take a name once this version occupies it,
+    // so every requirement on those names has to be met by it (or already be met)
+    fn fits(&self, candidate: &Candidate) -> bool {
+        self.misfit(candidate).is_none()
+    }
+
+    fn misfit(&self, candidate: &Candidate) -> Option<&Requirement> {
+        candidate.occupies()
+            .flat_map(|name| self.requirements.get(name).into_iter().flatten())
+            .find(|r| !candidate.satisfies(&r.link) && !self.is_satisfied(&r.link))
+    }
+
+    // why this version cannot be installed next to what has been decided, if it can't
+    fn clash(&self, candidate: &Candidate) -> Option<Clash> {
+        let same = |text: String, by: Option<&String>| Clash { one: text.clone(), many: text, by: by.cloned() };
+
+        if let Some(chosen) = self.decisions.get(&candidate.name) {
+            return Some(same(format!("cannot be installed as {} is already selected", chosen.pretty()), Some(&chosen.name)));
+        }
+
+        for name in candidate.occupies() {
+            if let Some(occupant) = self.occupied.get(name) {
+                let chosen = self.decisions.get(occupant);
+                let pretty = chosen.map(|c| c.pretty()).unwrap_or_else(|| occupant.clone());
+                let text = if name == candidate.name {
+                    format!("cannot be installed as {} replaces it", pretty)
+                } else {
+                    format!("cannot be installed as {} also replaces {}", pretty, name)
+                };
+                return Some(same(text, chosen.map(|c| &c.name)));
+            }
+        }
+
+        for link in &candidate.conflicts {
+            if let Some(other) = self.provided.get(&link.target).into_iter().flatten().find(|c| c.satisfies(link)) {
+                return Some(Clash {
+                    one: format!("conflicts with {}", other.pretty()),
+                    many: format!("conflict with {}", other.pretty()),
+                    by: Some(other.name.clone()),
+                });
+            }
+        }
+
+        for chosen in self.decisions.values() {
+            if chosen.conflicts.iter().any(|link| candidate.satisfies(link)) {
+                return Some(Clash {
+                    one: format!("cannot be installed as {} conflicts with it", chosen.pretty()),
+                    many: format!("cannot be installed as {} conflicts with them", chosen.pretty()),
+                    by: Some(chosen.name.clone()),
+                });
+            }
+        }
+
+        if let Some(link) = self.root_conflicts.iter().find(|link| candidate.satisfies(link)) {
+            return Some(same(format!("cannot be installed as root composer.json conflicts with {} {}", link.target, link.pretty_constraint), None));
+        }
+
+        None
+    }
+
+    fn decide(&mut self, candidate: &'a Candidate) -> Decision {
+        self.decisions.insert(candidate.name.clone(), candidate);
+
+        let occupied: Vec<String> = candidate.occupies().map(|n| n.to_string()).collect();
+        for name in &occupied {
+            self.occupied.insert(name.clone(), candidate.name.clone());
+        }
+
+        let provided: Vec<String> = occupied.iter().cloned()
+            .chain(candidate.provides.iter().map(|l| l.target.clone()))
+            .collect();
+        for name in &provided {
+            self.provided.entry(name.clone()).or_default().push(candidate);
+        }
+
+        let parent = Origin::Package(candidate.name.clone(), candidate.package.version.clone());
+        let mut required = Vec::new();
+        for link in &candidate.requires {
+            self.requirements.entry(link.target.clone()).or_default().push(Requirement {
+                link: link.clone(),
+                required_by: parent.clone(),
+            });
+            required.push(link.target.clone());
+        }
+
+        Decision { occupied, provided, required }
+    }
+
+    fn undo(&mut self, candidate: &Candidate, decision: Decision) {
+        self.decisions.remove(&candidate.name);
+        for name in &decision.occupied {
+            self.occupied.remove(name);
+        }
+        for name in &decision.provided {
+            if let Some(list) = self.provided.get_mut(name) {
+                list.pop();
+                if list.is_empty() {
+                    self.provided.remove(name);
+                }
+            }
+        }
+        for dep in &decision.required {
+            if let Some(list) = self.requirements.get_mut(dep) {
+                list.pop();
+                if list.is_empty() {
+                    self.requirements.remove(dep);
+                }
+            }
+        }
+    }
+
+    fn record_conflict(&mut self, name: &str) {
+        let depth = self.decisions.len();
+        if self.conflict.as_ref().is_some_and(|(d, _)| *d >= depth) {
+            return;
+        }
+        self.conflict = Some((depth, self.explain(name)));
+    }
+
+    // Build a composer-style derivation for why the requirements on `name` cannot be fulfilled:
+    // the chain of requirements that led to it, followed by the reason each option was rejected.
+    fn explain(&self, name: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut seen = Vec::new();
+        self.derive(name, &mut lines, &mut seen);
+
+        // for platform packages and versions below minimum-stability the lines above already say what is wrong
+        let all = self.pool.what_provides(name);
+        if all.is_empty() {
+            if !is_platform_package(name) && !self.pool.unstable.contains_key(name) {
+                lines.push(format!("{} could not be found in any version, it may be misspelled or not exist.", name));
+            }
+            return lines;
+        }
+
+        let open = self.open_links(name);
+        let options: Vec<&Candidate> = all.into_iter()
+            .filter(|c| open.iter().all(|link| c.satisfies(link)))
+            .collect();
+        if options.is_empty() {
+            if is_platform_package(name) {
+                return lines;
+            }
+            lines.push(format!("Therefore no version of {} satisfies all of the constraints above.", name));
+            return lines;
+        }
+
+        // group options by package and by the reason they were rejected
+        let mut rejected: BTreeMap<(String, String), (Rejection, Vec<&Candidate>)> = BTreeMap::new();
+        for candidate in &options {
+            let rejection = if let Some(clash) = self.clash(candidate) {
+                Rejection::Clash(clash)
+            } else if let Some(requirement) = self.misfit(candidate) {
+                let text = format!("cannot be installed as {} and it replaces {}", requirement.describe(), requirement.link.target);
+                Rejection::Clash(Clash { one: text.clone(), many: text, by: None })
+            } else if let Some(link) = self.unmet_requirement(candidate) {
+                Rejection::Requires(link)
+            } else {
+                continue;
+            };
+            let key = match &rejection {
+                Rejection::Requires(link) => format!("{} {}", link.target, link.pretty_constraint),
+                Rejection::Clash(clash) => clash.one.clone(),
+            };
+            rejected.entry((candidate.name.clone(), key))
+                .or_insert_with(|| (rejection, Vec::new()))
+                .1.push(candidate);
+        }
+
+        for ((pkg_name, _), (rejection, versions)) in &rejected {
+            let subject = format_versions(pkg_name, versions);
+            match rejection {
+                Rejection::Clash(clash) => {
+                    lines.push(format!("{} {}.", subject, if versions.len() == 1 { &clash.one } else { &clash.many }));
+                }
+                Rejection::Requires(link) => {
+                    let verb = if versions.len() == 1 { "requires" } else { "require" };
+                    lines.push(format!(
+                        "{} {} {} {} -> {}.",
+                        subject, verb, link.target, link.pretty_constraint, self.satisfiable_by(link),
+                    ));
+
+                    // how the selected version came to be required, before saying it doesn't match
+                    self.derive(&link.target, &mut lines, &mut seen);
+                    if let Some(chosen) = self.decisions.get(&link.target) {
+                        lines.push(format!(
+                            "{} is already selected, which does not match {}.",
+                            chosen.pretty(), link.pretty_constraint,
+                        ));
+                    }
+
+                    // matching versions that are ruled out by a replace or conflict
+                    for candidate in self.pool.what_provides(&link.target).into_iter().filter(|c| c.satisfies(link)) {
+                        if let Some(Clash { one, .. }) = self.clash(candidate) {
+                            let line = format!("{} {}.", format_versions(&candidate.name, &[candidate]), one);
+                            if !lines.contains(&line) {
+                                lines.push(line);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        lines.push(format!("Therefore {} cannot be installed.", name));
+        lines
+    }
+
+    // explain where the constraints on `name` come from, starting at the root
+    fn derive(&self, name: &str, lines: &mut Vec<String>, seen: &mut Vec<String>) {
+        if seen.iter().any(|n| n == name) {
+            return;
+        }
+        seen.push(name.to_string());
+
+        let requirements = self.requirements.get(name).map(|r| r.as_slice()).unwrap_or(&[]);
+
+        // a package picked to replace or provide something was required under that other name
+        if requirements.is_empty() && let Some(chosen) = self.decisions.get(name) {
+            for (target, reqs) in &self.requirements {
+                if target != name && reqs.iter().any(|r| chosen.satisfies(&r.link)) {
+                    self.derive(target, lines, seen);
+                }
+            }
+        }
+
+        for requirement in requirements {
+            if let Origin::Package(parent, _) = &requirement.required_by {
+                self.derive(parent, lines, seen);
+            }
+
+            let line = format!("{} -> {}.", requirement.describe(), self.satisfiable_by(&requirement.link));
+            if !lines.contains(&line) {
+                lines.push(line);
+            }
+        }
+    }
+
+    fn satisfiable_by(&self, link: &Link) -> String {
+        let matching: Vec<&Candidate> = self.pool.what_provides(&link.target).into_iter()
+            .filter(|c| c.satisfies(link))
+            .collect();
+        if matching.is_empty() && is_platform_package(&link.target) {
+            return match self.pool.platform.version(&link.target) {
+                Some(version) if link.target == "php" => format!("your php version ({}) does not satisfy that requirement", version),
+                Some(version) => format!("it has the wrong version installed ({})", version),
+                None => match link.target.strip_prefix("ext-") {
+                    Some(ext) => format!("it is missing from your system. Install or enable PHP's {} extension", ext),
+                    None => "it is missing from your system".to_string(),
+                },
+            };
+        }
+        if matching.is_empty() {
+            let unstable: Vec<&str> = self.pool.unstable.get(&link.target).into_iter().flatten()
+                .filter(|(_, version)| link.constraint.matches(version))
+                .map(|(pretty, _)| pretty.as_str())
+                .collect();
+            if unstable.is_empty() {
+                "found no matching package".to_string()
+            } else {
+                format!(
+                    "found {}[{}] but these do not match your minimum-stability",
+                    link.target, unstable.join(", "),
+                )
+            }
+        } else {
+            // one entry per package, since replacers and providers may also match
+            let mut by_package: BTreeMap<&str, Vec<&Candidate>> = BTreeMap::new();
+            for candidate in matching {
+                by_package.entry(candidate.name.as_str()).or_default().push(candidate);
+            }
+            let list: Vec<String> = by_package.iter()
+                .map(|(name, versions)| format_versions(name, versions))
+                .collect();
+            format!("satisfiable by {}", list.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, require: &[&str]) -> PackageVersion {
+        PackageVersion {
+            name: Some(name.into()),
+            version: "1.0.0".into(),
+            require: require.iter().map(|dep| (dep.to_string(), "^1.0".to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn release(name: &str, version: &str, require: &[(&str, &str)]) -> PackageVersion {
+        PackageVersion {
+            name: Some(name.into()),
+            version: version.into(),
+            require: require.iter().map(|(dep, constraint)| (dep.to_string(), constraint.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn links(links: &[(&str, &str)]) -> Vec<(String, String)> {
+        links.iter().map(|(name, constraint)| (name.to_string(), constraint.to_string())).collect()
+    }
+
+    fn pool_of(policy: StabilityPolicy, platform: Platform, releases: Vec<PackageVersion>) -> Pool {
+        let mut pool = Pool::new(policy, platform);
+        for package in releases {
+            let name = package.name.clone().unwrap();
+            pool.insert(&name, vec![package]);
+        }
+        pool
+    }
+
+    fn solve(releases: Vec<PackageVersion>, request: Request) -> Result<Vec<String>> {
+        let pool = pool_of(StabilityPolicy::default(), Platform::default(), releases);
+        let solution = Solver::new(&pool, &request)?.solve()?;
+        Ok(solution.iter().map(|p| format!("{} {}", p.name.as_deref().unwrap(), p.version)).collect())
+    }
+
+    // the "Problem 1" lines of a failed resolution
+    fn problem(pool: &Pool, request: Request) -> Vec<String> {
+        let error = Solver::new(pool, &request).unwrap().solve().unwrap_err().to_string();
+        let (_, lines) = error.split_once("Problem 1\n").unwrap();
+        lines.lines().map(|line| line.trim_start_matches("    - ").to_string()).collect()
+    }
+
+    fn requiring(require: &[(&str, &str)]) -> Request {
+        Request { require: links(require), ..Default::default() }
+    }
+
+    #[test]
+    fn undoes_the_newest_choice_when_it_leads_nowhere() {
+        // a/a 2.0.0 needs b/b ^2 through c/c, but the root only takes b/b ^1
+        let releases = vec![
+            release("a/a", "2.0.0", &[("c/c", "^1.0")]),
+            release("a/a", "1.0.0", &[]),
+            release("b/b", "1.0.0", &[]),
+            release("b/b", "2.0.0", &[]),
+            release("c/c", "1.0.0", &[("b/b", "^2.0")]),
+        ];
+        let solution = solve(releases, requiring(&[("a/a", "*"), ("b/b", "^1.0")])).unwrap();
+        assert_eq!(solution, ["a/a 1.0.0", "b/b 1.0.0"]);
+    }
+
+    #[test]
+    fn fails_when_no_combination_fits() {
+        let releases = vec![
+            release("a/a", "1.0.0", &[("b/b", "^2.0")]),
+            release("a/a", "1.1.0", &[("b/b", "^2.0")]),
+            release("b/b", "1.0.0", &[]),
+        ];
+        assert!(solve(releases, requiring(&[("a/a", "^1.0")])).is_err());
+    }
+
+    #[test]
+    fn gives_up_quickly_on_a_long_chain_missing_its_leaf() {
+        // every version of a/a needs some b/b, and so on down to e/e, which does not exist
+        let chain = ["a/a", "b/b", "c/c", "d/d", "e/e"];
+        let mut releases = Vec::new();
+        for (name, next) in chain.iter().zip(&chain[1..4]) {
+            for minor in 0..60 {
+                let version = format!("1.{}.0", minor);
+                let constraint = format!(">=1.{}", minor % 7);
+                releases.push(release(name, &version, &[(next, constraint.as_str()), ("x/x", "*")]));
+            }
+        }
+        for minor in 0..60 {
+            releases.push(release("d/d", &format!("1.{}.0", minor), &[("e/e", "^1.0")]));
+            releases.push(release("x/x", &format!("1.{}.0", minor), &[]));
+        }
+
+        let started = std::time::Instant::now();
+        assert!(solve(releases, requiring(&[("a/a", "*"), ("x/x", "*")])).is_err());
+        assert!(started.elapsed() < std::time::Duration::from_secs(2), "took {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn explains_two_paths_to_the_same_package() {
+        let pool = pool_of(StabilityPolicy::default(), Platform::default(), vec![
+            release("a/a", "1.0.0", &[("b/b", "^2.0")]),
+            release("b/b", "2.0.0", &[]),
+            release("b/b", "3.0.0", &[]),
+            release("c/c", "1.0.0", &[("b/b", "^3.0")]),
+        ]);
+        assert_eq!(problem(&pool, requiring(&[("a/a", "^1.0"), ("c/c", "^1.0")])), [
+            "Root composer.json requires c/c ^1.0 -> satisfiable by c/c[1.0.0].",
+            "c/c[1.0.0] requires b/b ^3.0 -> satisfiable by b/b[3.0.0].",
+            "Root composer.json requires a/a ^1.0 -> satisfiable by a/a[1.0.0].",
+            "a/a 1.0.0 requires b/b ^2.0 -> satisfiable by b/b[2.0.0].",
+            "b/b 2.0.0 is already selected, which does not match ^3.0.",
+            "b/b[3.0.0] cannot be installed as b/b 2.0.0 is already selected.",
+            "Therefore c/c cannot be installed.",
+        ]);
+    }
+
+    #[test]
+    fn explains_a_missing_package() {
+        let pool = pool_of(StabilityPolicy::default(), Platform::default(), vec![
+            release("a/a", "1.0.0", &[("b/b", "^1.0")]),
+        ]);
+        assert_eq!(problem(&pool, requiring(&[("a/a", "^1.0")])), [
+            "Root composer.json requires a/a ^1.0 -> satisfiable by a/a[1.0.0].",
+            "a/a[1.0.0] requires b/b ^1.0 -> found no matching package.",
+            "Therefore a/a cannot be installed.",
+        ]);
+        assert_eq!(problem(&pool, requiring(&[("a/b", "^1.0")])), [
+            "Root composer.json requires a/b ^1.0 -> found no matching package.",
+            "a/b could not be found in any version, it may be misspelled or not exist.",
+        ]);
+    }
+
+    #[test]
+    fn explains_versions_below_minimum_stability() {
+        let pool = pool_of(StabilityPolicy::default(), Platform::default(), vec![
+            release("a/a", "1.0.0", &[("b/b", "^1.0")]),
+            release("b/b", "1.0.0-beta2", &[]),
+            release("b/b", "1.1.0-RC1", &[]),
+        ]);
+        assert_eq!(problem(&pool, requiring(&[("a/a", "^1.0")])), [
+            "Root composer.json requires a/a ^1.0 -> satisfiable by a/a[1.0.0].",
+            "a/a[1.0.0] requires b/b ^1.0 -> found b/b[1.0.0-beta2, 1.1.0-RC1] but these do not match your minimum-stability.",
+            "Therefore a/a cannot be installed.",
+        ]);
+    }
+
+    #[test]
+    fn explains_platform_requirements() {
+        let overrides = BTreeMap::from([
+            ("php".to_string(), serde_json::json!("7.4.33")),
+            ("ext-intl".to_string(), serde_json::json!(false)),
+        ]);
+        let pool = pool_of(StabilityPolicy::default(), Platform::default().with_overrides(&overrides), vec![
+            release("a/a", "1.0.0", &[("php", ">=8.1")]),
+            release("a/a", "0.9.0", &[("ext-intl", "*")]),
+        ]);
+        assert_eq!(problem(&pool, requiring(&[("a/a", "*")])), [
+            "Root composer.json requires a/a * -> satisfiable by a/a[0.9.0, 1.0.0].",
+            "a/a[0.9.0] requires ext-intl * -> it is missing from your system. Install or enable PHP's intl extension.",
+            "a/a[1.0.0] requires php >=8.1 -> your php version (7.4.33) does not satisfy that requirement.",
+            "Therefore a/a cannot be installed.",
+        ]);
+    }
+
+    #[test]
+    fn takes_replaced_names_from_the_replacing_package() {
+        let mut symfony = release("symfony/symfony", "5.4.0", &[("symfony/polyfill-mbstring", "^1.0")]);
+        symfony.replace = BTreeMap::from([("symfony/console".to_string(), "self.version".to_string())]);
+        let releases = vec![
+            symfony,
+            release("symfony/console", "5.4.0", &[]),
+            release("symfony/polyfill-mbstring", "1.27.0", &[]),
+            release("acme/cli", "1.0.0", &[("symfony/console", "^5.4")]),
+        ];
+
+        let request = requiring(&[("symfony/symfony", "^5.4"), ("acme/cli", "^1.0")]);
+        assert_eq!(solve(releases.clone(), request).unwrap(), ["acme/cli 1.0.0", "symfony/polyfill-mbstring 1.27.0", "symfony/symfony 5.4.0"]);
+
+        // a root that replaces the polyfill keeps it out, whatever requires it
+        let request = Request {
+            replace: links(&[("symfony/polyfill-mbstring", "*")]),
+            ..requiring(&[("symfony/symfony", "^5.4")])
+        };
+        assert_eq!(solve(releases.clone(), request).unwrap(), ["symfony/symfony 5.4.0"]);
+
+        // nothing else can be installed under a name symfony/symfony already takes
+        let request = requiring(&[("symfony/symfony", "^5.4"), ("symfony/console", "5.3.*")]);
+        assert!(solve(releases, request).is_err());
+    }
+
+    #[test]
+    fn fulfils_virtual_packages_with_providers() {
+        let mut monolog = release("monolog/monolog", "3.5.0", &[("psr/log", "^3.0")]);
+        monolog.provide = BTreeMap::from([("psr/log-implementation".to_string(), "3.0.0".to_string())]);
+        let releases = vec![
+            monolog,
+            release("psr/log", "3.0.0", &[]),
+            release("acme/app", "1.0.0", &[("psr/log-implementation", "^3.0")]),
+        ];
+
+        let request = requiring(&[("acme/app", "^1.0")]);
+        assert_eq!(solve(releases.clone(), request).unwrap(), ["acme/app 1.0.0", "monolog/monolog 3.5.0", "psr/log 3.0.0"]);
+
+        // the provided version still has to match
+        let request = requiring(&[("psr/log-implementation", "^1.0")]);
+        assert!(solve(releases.clone(), request).is_err());
+
+        // a root that provides the implementation itself needs no logger
+        let request = Request {
+            provide: links(&[("psr/log-implementation", "3.0.0")]),
+            ..requiring(&[("acme/app", "^1.0")])
+        };
+        assert_eq!(solve(releases, request).unwrap(), ["acme/app 1.0.0"]);
+    }
+
+    #[test]
+    fn keeps_conflicting_versions_apart() {
+        let mut newest = release("a/a", "2.0.0", &[]);
+        newest.conflict = BTreeMap::from([("b/b".to_string(), "<1.5".to_string())]);
+        let releases = vec![
+            newest,
+            release("a/a", "1.0.0", &[]),
+            release("b/b", "1.0.0", &[]),
+            release("b/b", "1.4.0", &[]),
+        ];
+
+        let request = requiring(&[("a/a", "*"), ("b/b", "^1.0")]);
+        assert_eq!(solve(releases.clone(), request).unwrap(), ["a/a 1.0.0", "b/b 1.4.0"]);
+
+        let request = Request {
+            conflict: links(&[("b/b", "1.4.0")]),
+            ..requiring(&[("b/b", "^1.0")])
+        };
+        assert_eq!(solve(releases.clone(), request).unwrap(), ["b/b 1.0.0"]);
+
+        let request = requiring(&[("a/a", "^2.0"), ("b/b", "^1.0")]);
+        assert!(solve(releases, request).is_err());
+    }
+
+    #[test]
+    fn drops_orphans_when_a_root_requirement_goes() {
+        // what `remove` resolves: every locked package pinned, one root requirement gone
+        let locked = vec![
+            release("a/a", "1.0.0", &[("shared/s", "^1.0"), ("orphan/o", "^1.0")]),
+            release("b/b", "1.0.0", &[("shared/s", "^1.0")]),
+            release("shared/s", "1.0.0", &[]),
+            release("orphan/o", "1.0.0", &[]),
+        ];
+        let mut pool = Pool::new(StabilityPolicy::default(), Platform::default());
+        for mut package in locked {
+            package.version_normalized = crate::version::normalize(&package.version).unwrap();
+            pool.pin(package);
+        }
+        // newer releases don't get in while everything is pinned
+        pool.insert("shared/s", vec![release("shared/s", "1.1.0", &[])]);
+        pool.insert("b/b", vec![release("b/b", "1.1.0", &[])]);
+
+        let solution = Solver::new(&pool, &requiring(&[("b/b", "^1.0")])).unwrap().solve().unwrap();
+        let solution: Vec<String> = solution.iter().map(|p| format!("{} {}", p.name.as_deref().unwrap(), p.version)).collect();
+        assert_eq!(solution, ["b/b 1.0.0", "shared/s 1.0.0"]);
+    }
+
+    #[test]
+    fn orders_candidates_by_stability_policy() {
+        let releases = || vec![
+            release("a/a", "1.0.0", &[]),
+            release("a/a", "1.1.0-beta1", &[]),
+            release("a/a", "1.1.0-RC1", &[]),
+            release("a/a", "1.2.0-alpha2", &[]),
+        ];
+        // (minimum-stability, flags, prefer-stable, candidates in the order they are tried)
+        let cases = [
+            (Stability::Stable, vec![], false, vec!["1.0.0"]),
+            (Stability::Beta, vec![], false, vec!["1.1.0-RC1", "1.1.0-beta1", "1.0.0"]),
+            (Stability::Alpha, vec![], false, vec!["1.2.0-alpha2", "1.1.0-RC1", "1.1.0-beta1", "1.0.0"]),
+            (Stability::Alpha, vec![], true, vec!["1.0.0", "1.1.0-RC1", "1.1.0-beta1", "1.2.0-alpha2"]),
+            (Stability::Stable, vec![("a/a", Stability::RC)], false, vec!["1.1.0-RC1", "1.0.0"]),
+            (Stability::Stable, vec![("a/a", Stability::Beta)], true, vec!["1.0.0", "1.1.0-RC1", "1.1.0-beta1"]),
+        ];
+        for (minimum, flags, prefer_stable, expected) in cases {
+            let flags = flags.into_iter().map(|(name, stability)| (name.to_string(), stability)).collect();
+            let policy = StabilityPolicy { minimum, flags, prefer_stable };
+            let pool = pool_of(policy.clone(), Platform::default(), releases());
+            let tried: Vec<&str> = pool.versions("a/a").iter().map(|c| c.package.version.as_str()).collect();
+            assert_eq!(tried, expected, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn splits_dev_packages_from_the_rest() {
+        let mut logger = package("acme/logger", &[]);
+        logger.provide.insert("psr/log-implementation".into(), "1.0.0".into());
+        let mut fork = package("acme/http-fork", &["acme/shared"]);
+        fork.replace.insert("old/http".into(), "1.0.0".into());
+        let mut cache = package("acme/cache", &[]);
+        cache.provide.insert("psr/cache-implementation".into(), "1.0.0".into());
+        let mut faker = package("acme/faker", &[]);
+        faker.replace.insert("fzaninotto/faker".into(), "1.0.0".into());
+        let packages = vec![
+            package("acme/app", &["php", "ext-json", "psr/log-implementation", "old/http"]),
+            logger,
+            fork,
+            // required by both trees, so it stays with the non-dev packages
+            package("acme/shared", &[]),
+            package("acme/phpunit", &["acme/shared", "psr/cache-implementation", "fzaninotto/faker"]),
+            cache,
+            faker,
+        ];
+        let names = |packages: &[PackageVersion]| -> Vec<String> {
+            let mut names: Vec<String> = packages.iter().filter_map(|p| p.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        let (packages, packages_dev) = partition_dev(packages, &links(&[("acme/app", "*")]));
+        assert_eq!(names(&packages), ["acme/app", "acme/http-fork", "acme/logger", "acme/shared"]);
+        assert_eq!(names(&packages_dev), ["acme/cache", "acme/faker", "acme/phpunit"]);
+    }
+
+    #[test]
+    fn allows_listed_packages_and_their_dependencies() {
+        // symfony/console -> symfony/string -> symfony/polyfill-mbstring, and psr/log which the root requires too
+        let locked = [
+            package("symfony/console", &["php", "symfony/string", "psr/log"]),
+            package("symfony/string", &["symfony/polyfill-mbstring"]),
+            package("symfony/polyfill-mbstring", &[]),
+            package("psr/log", &[]),
+            package("monolog/monolog", &["psr/log"]),
+        ];
+        let roots = [("symfony/console".to_string(), "^6.0".to_string()), ("psr/log".to_string(), "^3.0".to_string())];
+        let allowed = |patterns: &[&str], reach| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            let mut names: Vec<String> = allowed_updates(&locked, &patterns, &roots, reach).into_iter().collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(allowed(&["symfony/console"], UpdateReach::Listed), ["symfony/console"]);
+        assert_eq!(allowed(&["Symfony/*"], UpdateReach::Listed), ["symfony/console", "symfony/polyfill-mbstring", "symfony/string"]);
+        assert_eq!(allowed(&["symfony/console"], UpdateReach::Dependencies), ["symfony/console", "symfony/polyfill-mbstring", "symfony/string"]);
+        assert_eq!(
+            allowed(&["symfony/console"], UpdateReach::AllDependencies),
+            ["psr/log", "symfony/console", "symfony/polyfill-mbstring", "symfony/string"],
+        );
+        assert!(allowed(&["unknown/*"], UpdateReach::AllDependencies).is_empty());
+    }
+}
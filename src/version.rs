@@ -0,0 +1,482 @@
+use std::cmp::Ordering;
+use std::fmt;
+use anyhow::{bail, Result};
+
+// Composer's stability levels, from least to most stable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stability {
+    Dev,
+    Alpha,
+    Beta,
+    RC,
+    Stable,
+}
+
+impl Stability {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "dev" => Some(Stability::Dev),
+            "alpha" => Some(Stability::Alpha),
+            "beta" => Some(Stability::Beta),
+            "rc" => Some(Stability::RC),
+            "stable" => Some(Stability::Stable),
+            _ => None,
+        }
+    }
+
+    // the numeric form composer stores in composer.lock's "stability-flags" (lower is more stable)
+    pub fn priority(&self) -> u8 {
+        match self {
+            Stability::Stable => 0,
+            Stability::RC => 5,
+            Stability::Beta => 10,
+            Stability::Alpha => 15,
+            Stability::Dev => 20,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stability::Dev => "dev",
+            Stability::Alpha => "alpha",
+            Stability::Beta => "beta",
+            Stability::RC => "RC",
+            Stability::Stable => "stable",
+        }
+    }
+}
+
+// A version as composer understands it: either four numeric segments with an optional
+// stability suffix ("1.2.3.0-beta2", "2.9999999.9999999.9999999-dev") or a named branch ("dev-main").
+// Ordering follows PHP's version_compare() on the normalized string, which is what composer uses.
+#[derive(Debug, Clone)]
+pub struct PhpVersion {
+    normalized: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Number(u64),
+    // rank of a non-numeric segment as version_compare() sees it: dev < alpha < beta < RC < number < patch
+    Special(i8),
+}
+
+impl PhpVersion {
+    // accepts anything composer would accept in a "version" field
+    pub fn parse(version: &str) -> Result<Self> {
+        Ok(Self::from_normalized(&normalize(version)?))
+    }
+
+    // trust an already normalized string (e.g. "version_normalized" from packagist)
+    pub fn from_normalized(normalized: &str) -> Self {
+        let tokens = if normalized.starts_with("dev-") {
+            Vec::new()
+        } else {
+            tokenize(normalized)
+        };
+        Self { normalized: normalized.to_string(), tokens }
+    }
+
+    pub fn normalized(&self) -> &str {
+        &self.normalized
+    }
+
+    pub fn is_branch(&self) -> bool {
+        self.normalized.starts_with("dev-")
+    }
+
+    pub fn stability(&self) -> Stability {
+        parse_stability(&self.normalized)
+    }
+}
+
+impl fmt::Display for PhpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.normalized)
+    }
+}
+
+impl Ord for PhpVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.is_branch(), other.is_branch()) {
+            // named branches have no place on the numeric line; keep them below every release
+            (true, true) => self.normalized.cmp(&other.normalized),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => compare_tokens(&self.tokens, &other.tokens),
+        }
+    }
+}
+
+impl PartialOrd for PhpVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PhpVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PhpVersion {}
+
+// Port of composer's VersionParser::normalize():
+// "v1.2" -> "1.2.0.0", "1.0.0-b2" -> "1.0.0.0-beta2", "2.x-dev" -> "2.9999999.9999999.9999999-dev", "main" stays a branch
+pub fn normalize(version: &str) -> Result<String> {
+    let original = version;
+    let mut version = version.trim();
+
+    // strip off inline aliasing ("dev-main as 1.0.x-dev")
+    if let Some((real, _alias)) = version.split_once(" as ") {
+        version = real.trim();
+    }
+
+    // strip off stability flag
+    if let Some((rest, flag)) = version.rsplit_once('@')
+        && Stability::parse(flag).is_some()
+    {
+        version = rest;
+    }
+
+    if matches!(version, "master" | "trunk" | "default") {
+        return Ok(format!("dev-{}", version));
+    }
+
+    if version.len() >= 4 && version[..4].eq_ignore_ascii_case("dev-") {
+        return Ok(format!("dev-{}", &version[4..]));
+    }
+
+    // strip off build metadata
+    if let Some((rest, _build)) = version.split_once('+') {
+        version = rest;
+    }
+
+    if let Some(normalized) = normalize_classic(version) {
+        return Ok(normalized);
+    }
+
+    // numeric branches: "2.1.x-dev", "2.x-dev"
+    let lower = version.to_lowercase();
+    if let Some(branch) = lower.strip_suffix("dev") {
+        let branch = branch.trim_end_matches(['.', '-']);
+        if let Some(normalized) = normalize_branch(branch) {
+            return Ok(normalized);
+        }
+    }
+
+    bail!("Invalid version string \"{}\"", original)
+}
+
+// "1.2", "v1.2.3.4-RC1", "1.0.0-beta.2-dev"
+fn normalize_classic(version: &str) -> Option<String> {
+    let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+
+    let numbers_end = version.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(version.len());
+    let (numbers, modifier) = version.split_at(numbers_end);
+
+    let segments: Vec<&str> = numbers.split('.').collect();
+    if segments.len() > 4 || segments.iter().any(|s| s.is_empty() || !s.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    if segments[0].len() > 5 {
+        // composer reads long leading numbers as dates; keep them out of the classic form
+        return normalize_date(numbers, modifier);
+    }
+
+    let mut normalized: Vec<String> = segments.iter().map(|s| s.to_string()).collect();
+    while normalized.len() < 4 {
+        normalized.push("0".to_string());
+    }
+
+    let mut result = normalized.join(".");
+    result.push_str(&parse_modifier(modifier)?);
+    Some(result)
+}
+
+// "20231201" / "2023.12.01": date based versions keep their own segment count
+fn normalize_date(numbers: &str, modifier: &str) -> Option<String> {
+    if numbers.len() < 4 || !numbers.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut result = numbers.to_string();
+    result.push_str(&parse_modifier(modifier)?);
+    Some(result)
+}
+
+// "-beta.2-dev" -> "-beta2-dev", "RC1" -> "-RC1", "pl3" -> "-patch3", "-stable" -> ""
+// mirrors composer's modifier regex: [._-]?(?:(stable|beta|b|RC|alpha|a|patch|pl|p)((?:[.-]?\d+)*+)?)?([.-]?dev)?
+fn parse_modifier(modifier: &str) -> Option<String> {
+    const STABILITIES: [(&str, &str); 9] = [
+        ("stable", ""),
+        ("beta", "beta"),
+        ("b", "beta"),
+        ("rc", "RC"),
+        ("alpha", "alpha"),
+        ("a", "alpha"),
+        ("patch", "patch"),
+        ("pl", "patch"),
+        ("p", "patch"),
+    ];
+
+    let lower = modifier.to_lowercase();
+    let mut rest = lower.strip_prefix(['.', '_', '-']).unwrap_or(&lower);
+    let mut result = String::new();
+
+    if let Some((name, expanded)) = STABILITIES.iter().find(|(name, _)| rest.starts_with(name)) {
+        rest = &rest[name.len()..];
+
+        // any number of "[.-]?digits" groups
+        let mut number = String::new();
+        loop {
+            let body = rest.strip_prefix(['.', '-']).unwrap_or(rest);
+            let digits = body.find(|c: char| !c.is_ascii_digit()).unwrap_or(body.len());
+            if digits == 0 {
+                break;
+            }
+            number.push_str(&rest[..rest.len() - body.len() + digits]);
+            rest = &body[digits..];
+        }
+
+        if *name != "stable" {
+            result = format!("-{}{}", expanded, number.trim_start_matches(['.', '-']));
+        }
+    }
+
+    let dev = rest.strip_prefix(['.', '-']).unwrap_or(rest);
+    if dev == "dev" {
+        result.push_str("-dev");
+        rest = "";
+    }
+
+    rest.is_empty().then_some(result)
+}
+
+// "2.1" -> "2.1.9999999.9999999-dev", "2.x" -> "2.9999999.9999999.9999999-dev"
+fn normalize_branch(name: &str) -> Option<String> {
+    let name = name.strip_prefix(['v', 'V']).unwrap_or(name);
+    let segments: Vec<&str> = name.split('.').collect();
+    if segments.is_empty() || segments.len() > 4 {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let wildcard = matches!(*segment, "x" | "X" | "*");
+        if segment.is_empty() || (!wildcard && !segment.chars().all(|c| c.is_ascii_digit())) || (i == 0 && wildcard) {
+            return None;
+        }
+        parts.push(if wildcard { "9999999".to_string() } else { segment.to_string() });
+    }
+    while parts.len() < 4 {
+        parts.push("9999999".to_string());
+    }
+
+    Some(format!("{}-dev", parts.join(".")))
+}
+
+// the version a vcs branch is published as: "2.1" -> "2.1.x-dev", "main" -> "dev-main"
+pub fn branch_version(branch: &str) -> String {
+    match normalize_branch(branch) {
+        Some(normalized) => {
+            let mut pretty = normalized.replace(".9999999", ".x");
+            while pretty.contains(".x.x") {
+                pretty = pretty.replace(".x.x", ".x");
+            }
+            pretty
+        }
+        None => format!("dev-{}", branch),
+    }
+}
+
+// port of VersionParser::parseStability()
+pub fn parse_stability(version: &str) -> Stability {
+    let version = version.split('#').next().unwrap_or(version).to_lowercase();
+    if version.starts_with("dev-") || version.ends_with("-dev") || version.ends_with(".dev") {
+        return Stability::Dev;
+    }
+
+    let modifier_start = version.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == 'v')).unwrap_or(version.len());
+    let modifier = version[modifier_start..].trim_start_matches(['.', '_', '-']);
+    if modifier.starts_with("dev") {
+        Stability::Dev
+    } else if modifier.starts_with("alpha") || modifier.starts_with('a') {
+        Stability::Alpha
+    } else if modifier.starts_with("beta") || modifier.starts_with('b') {
+        Stability::Beta
+    } else if modifier.starts_with("rc") {
+        Stability::RC
+    } else {
+        Stability::Stable
+    }
+}
+
+// PHP's version_compare() canonicalization: split on separators and on digit/letter boundaries
+fn tokenize(version: &str) -> Vec<Token> {
+    let mut segments: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut last: Option<char> = None;
+
+    for c in version.chars() {
+        let boundary = match last {
+            Some(l) => c.is_ascii_alphanumeric() && l.is_ascii_alphanumeric() && l.is_ascii_digit() != c.is_ascii_digit(),
+            None => false,
+        };
+        if (!c.is_ascii_alphanumeric() || boundary) && !current.is_empty() {
+            segments.push(std::mem::take(&mut current));
+        }
+        if c.is_ascii_alphanumeric() {
+            current.push(c);
+        }
+        last = Some(c);
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+
+    segments.iter()
+        .map(|s| match s.parse::<u64>() {
+            Ok(n) => Token::Number(n),
+            Err(_) => Token::Special(special_rank(s)),
+        })
+        .collect()
+}
+
+// version_compare()'s "special forms", matched by prefix in this order
+fn special_rank(form: &str) -> i8 {
+    const FORMS: [(&str, i8); 9] = [
+        ("dev", 0),
+        ("alpha", 1),
+        ("a", 1),
+        ("beta", 2),
+        ("b", 2),
+        ("RC", 3),
+        ("rc", 3),
+        ("pl", 5),
+        ("p", 5),
+    ];
+    FORMS.iter()
+        .find(|(name, _)| form.starts_with(name))
+        .map(|(_, rank)| *rank)
+        .unwrap_or(-6)
+}
+
+// a plain number ranks between RC and patch
+const NUMBER_RANK: i8 = 4;
+
+fn compare_tokens(a: &[Token], b: &[Token]) -> Ordering {
+    for (x, y) in a.iter().zip(b.iter()) {
+        let ordering = match (x, y) {
+            (Token::Number(x), Token::Number(y)) => x.cmp(y),
+            (Token::Special(x), Token::Special(y)) => x.cmp(y),
+            (Token::Number(_), Token::Special(y)) => NUMBER_RANK.cmp(y),
+            (Token::Special(x), Token::Number(_)) => x.cmp(&NUMBER_RANK),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    // a trailing number makes a version newer, a trailing "beta"/"dev" makes it older
+    match a.len().cmp(&b.len()) {
+        Ordering::Equal => Ordering::Equal,
+        Ordering::Greater => match a[b.len()] {
+            Token::Number(_) => Ordering::Greater,
+            Token::Special(rank) => rank.cmp(&NUMBER_RANK),
+        },
+        Ordering::Less => match b[a.len()] {
+            Token::Number(_) => Ordering::Less,
+            Token::Special(rank) => NUMBER_RANK.cmp(&rank),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_like_composer() {
+        let cases = [
+            ("1.0.0", "1.0.0.0"),
+            ("1.2.3.4", "1.2.3.4"),
+            ("v1.0", "1.0.0.0"),
+            ("1", "1.0.0.0"),
+            ("1.0.0-beta2", "1.0.0.0-beta2"),
+            ("1.0.0-b2", "1.0.0.0-beta2"),
+            ("1.0.0beta.2", "1.0.0.0-beta2"),
+            ("v1.0-RC1", "1.0.0.0-RC1"),
+            ("1.0.0-rc.1", "1.0.0.0-RC1"),
+            ("1.0.0-alpha", "1.0.0.0-alpha"),
+            ("1.0.0-a3", "1.0.0.0-alpha3"),
+            ("1.0.0-pl3", "1.0.0.0-patch3"),
+            ("1.0.0-stable", "1.0.0.0"),
+            ("1.0-dev", "1.0.0.0-dev"),
+            ("1.0.0-beta2-dev", "1.0.0.0-beta2-dev"),
+            ("1.0.0+build.5", "1.0.0.0"),
+            ("2.x-dev", "2.9999999.9999999.9999999-dev"),
+            ("2.1.x-dev", "2.1.9999999.9999999-dev"),
+            ("dev-main", "dev-main"),
+            ("dev-feature/foo", "dev-feature/foo"),
+            ("master", "dev-master"),
+            ("1.0.0@beta", "1.0.0.0"),
+            ("dev-main as 1.0.x-dev", "dev-main"),
+            ("20231201", "20231201"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize(input).unwrap(), expected, "normalizing {}", input);
+        }
+
+        for invalid in ["", "foo", "1.0.0.0.0", "1.0-gamma", "x.1-dev"] {
+            assert!(normalize(invalid).is_err(), "\"{}\" should not normalize", invalid);
+        }
+    }
+
+    #[test]
+    fn orders_like_version_compare() {
+        let ordered = [
+            "dev-main",
+            "1.0.0.0-dev",
+            "1.0.0.0-alpha1",
+            "1.0.0.0-alpha2",
+            "1.0.0.0-beta1",
+            "1.0.0.0-beta2-dev",
+            "1.0.0.0-beta2",
+            "1.0.0.0-RC1",
+            "1.0.0.0",
+            "1.0.0.0-patch1",
+            "1.0.0.1",
+            "1.2.3.0",
+            "1.2.3.4",
+            "1.10.0.0",
+            "2.9999999.9999999.9999999-dev",
+        ];
+        for pair in ordered.windows(2) {
+            let (a, b) = (PhpVersion::from_normalized(pair[0]), PhpVersion::from_normalized(pair[1]));
+            assert!(a < b, "{} should sort before {}", a, b);
+        }
+    }
+
+    #[test]
+    fn reads_stability() {
+        assert_eq!(parse_stability("1.0.0.0"), Stability::Stable);
+        assert_eq!(parse_stability("1.0.0.0-patch1"), Stability::Stable);
+        assert_eq!(parse_stability("1.0.0.0-RC2"), Stability::RC);
+        assert_eq!(parse_stability("1.0.0.0-beta2"), Stability::Beta);
+        assert_eq!(parse_stability("1.0.0.0-alpha1"), Stability::Alpha);
+        assert_eq!(parse_stability("1.0.0.0-beta2-dev"), Stability::Dev);
+        assert_eq!(parse_stability("dev-main"), Stability::Dev);
+        assert_eq!(parse_stability("2.9999999.9999999.9999999-dev"), Stability::Dev);
+    }
+
+    #[test]
+    fn names_branches() {
+        assert_eq!(branch_version("main"), "dev-main");
+        assert_eq!(branch_version("feature/foo"), "dev-feature/foo");
+        assert_eq!(branch_version("2.x"), "2.x-dev");
+        assert_eq!(branch_version("2.1"), "2.1.x-dev");
+        assert_eq!(branch_version("v3.0.x"), "3.0.x-dev");
+    }
+}